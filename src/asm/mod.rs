@@ -0,0 +1,618 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// A contiguous block of words produced by one `.ORIG`/`.END` pair.
+pub struct Segment {
+    pub origin: u16,
+    pub words: Vec<u16>,
+    /// The (1-indexed) source line each word was assembled from.
+    pub lines: Vec<usize>,
+}
+
+pub struct Program {
+    pub segments: Vec<Segment>,
+    /// Labels and their addresses, in the order they were defined.
+    pub symbols: Vec<(String, u16)>,
+}
+
+#[derive(Debug)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+}
+
+/// A single non-empty source line, split into its optional label, its opcode or directive, and its operands.
+struct Statement {
+    line: usize,
+    label: Option<String>,
+    op: Option<String>,
+    operands: Vec<Token>,
+}
+
+const OPCODES: [&str; 23] = [
+    "ADD", "AND", "NOT", "JMP", "RET", "JSR", "JSRR", "LD", "LDI", "LDR", "LEA", "ST", "STI", "STR", "TRAP", "RTI",
+    "GETC", "OUT", "PUTS", "IN", "PUTSP", "HALT", "NOP",
+];
+
+const DIRECTIVES: [&str; 5] = [".ORIG", ".FILL", ".BLKW", ".STRINGZ", ".END"];
+
+fn is_branch(op: &str) -> bool {
+    matches!(op, "BR" | "BRN" | "BRZ" | "BRP" | "BRNZ" | "BRNP" | "BRZP" | "BRNZP")
+}
+
+fn is_op(word: &str) -> bool {
+    let upper = word.to_ascii_uppercase();
+    OPCODES.contains(&upper.as_str()) || DIRECTIVES.contains(&upper.as_str()) || is_branch(&upper)
+}
+
+fn error<T>(line: usize, message: impl Into<String>) -> Result<T, AsmError> {
+    Err(AsmError { line, message: message.into() })
+}
+
+fn tokenize(line: usize, text: &str) -> Result<Vec<Token>, AsmError> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+        } else if c == ';' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => s.push('\n'),
+                        Some('t') => s.push('\t'),
+                        Some('r') => s.push('\r'),
+                        Some('0') => s.push('\0'),
+                        Some('e') => s.push('\x1b'),
+                        Some(e @ ('\\' | '"')) => s.push(e),
+                        Some(e) => return error(line, format!("unknown escape sequence \\{}", e)),
+                        None => return error(line, "unterminated string literal"),
+                    },
+                    Some(e) => s.push(e),
+                    None => return error(line, "unterminated string literal"),
+                }
+            }
+            tokens.push(Token::Str(s));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == ',' || c == ';' || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_statement(line: usize, text: &str) -> Result<Option<Statement>, AsmError> {
+    let mut tokens = tokenize(line, text)?.into_iter();
+    let mut statement = Statement { line, label: None, op: None, operands: vec![] };
+    let first = match tokens.next() {
+        Some(Token::Word(w)) => w,
+        Some(Token::Str(_)) => return error(line, "unexpected string literal"),
+        None => return Ok(None),
+    };
+    if is_op(&first) {
+        statement.op = Some(first.to_ascii_uppercase());
+    } else {
+        let label = first.strip_suffix(':').unwrap_or(&first).to_string();
+        if !label.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return error(line, format!("invalid label or opcode `{}`", first));
+        }
+        statement.label = Some(label);
+        match tokens.next() {
+            Some(Token::Word(w)) if is_op(&w) => statement.op = Some(w.to_ascii_uppercase()),
+            Some(Token::Word(w)) => return error(line, format!("unknown opcode `{}`", w)),
+            Some(Token::Str(_)) => return error(line, "unexpected string literal"),
+            None => {}
+        }
+    }
+    statement.operands = tokens.collect();
+    Ok(Some(statement))
+}
+
+/// Parses a numeric literal in any of the forms accepted by LC-3 assemblers: `#10`, `10`, `x1F`, `0x1F` or `b1010`.
+pub fn parse_number(s: &str) -> Option<i32> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let value = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(hex) = s.strip_prefix('x').or_else(|| s.strip_prefix('X')) {
+        let (negative, hex) = match hex.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, hex),
+        };
+        let v = i32::from_str_radix(hex, 16).ok()?;
+        if negative { -v } else { v }
+    } else if let Some(bin) = s.strip_prefix('b').or_else(|| s.strip_prefix('B')) {
+        i32::from_str_radix(bin, 2).ok()?
+    } else {
+        s.strip_prefix('#').unwrap_or(s).parse::<i32>().ok()?
+    };
+    if negative {
+        Some(-value)
+    } else {
+        Some(value)
+    }
+}
+
+fn parse_register(s: &str) -> Option<u16> {
+    let s = s.strip_prefix('R').or_else(|| s.strip_prefix('r'))?;
+    match s.parse::<u16>() {
+        Ok(r) if r < 8 => Some(r),
+        _ => None,
+    }
+}
+
+/// Number of words the statement occupies in memory.
+fn statement_size(statement: &Statement) -> Result<u16, AsmError> {
+    let line = statement.line;
+    Ok(match statement.op.as_deref() {
+        None | Some(".ORIG") | Some(".END") => 0,
+        Some(".BLKW") => match statement.operands.first() {
+            Some(Token::Word(w)) => match parse_number(w) {
+                Some(n) if (0..=0xFFFF).contains(&n) => n as u16,
+                _ => return error(line, format!("invalid .BLKW size `{}`", w)),
+            },
+            _ => return error(line, ".BLKW requires a size"),
+        },
+        Some(".STRINGZ") => match statement.operands.first() {
+            Some(Token::Str(s)) => s.chars().count() as u16 + 1,
+            _ => return error(line, ".STRINGZ requires a string literal"),
+        },
+        Some(_) => 1,
+    })
+}
+
+struct Encoder<'a> {
+    symbols: &'a HashMap<String, u16>,
+    statement: &'a Statement,
+    /// Address of the word being encoded.
+    address: u16,
+}
+
+impl Encoder<'_> {
+    fn err<T>(&self, message: impl Into<String>) -> Result<T, AsmError> {
+        error(self.statement.line, message)
+    }
+
+    fn expect_operands(&self, n: usize) -> Result<(), AsmError> {
+        let found = self.statement.operands.len();
+        if found != n {
+            return self.err(format!(
+                "{} expects {} operand{}, found {}",
+                self.statement.op.as_deref().unwrap_or(""),
+                n,
+                if n == 1 { "" } else { "s" },
+                found,
+            ));
+        }
+        Ok(())
+    }
+
+    fn word(&self, i: usize) -> Result<&str, AsmError> {
+        match &self.statement.operands[i] {
+            Token::Word(w) => Ok(w),
+            Token::Str(_) => self.err("unexpected string literal"),
+        }
+    }
+
+    fn register(&self, i: usize) -> Result<u16, AsmError> {
+        let w = self.word(i)?;
+        match parse_register(w) {
+            Some(r) => Ok(r),
+            None => self.err(format!("expected a register, found `{}`", w)),
+        }
+    }
+
+    /// Encodes operand `i` as a signed immediate of `len` bits.
+    fn immediate(&self, i: usize, len: u32) -> Result<u16, AsmError> {
+        let w = self.word(i)?;
+        let value = match parse_number(w) {
+            Some(v) => v,
+            None => return self.err(format!("expected an immediate value, found `{}`", w)),
+        };
+        let (min, max) = (-(1 << (len - 1)), (1 << (len - 1)) - 1);
+        if value < min || value > max {
+            return self.err(format!("immediate value {} does not fit in {} bits (range {} to {})", value, len, min, max));
+        }
+        Ok((value as u16) & ((1 << len) - 1))
+    }
+
+    /// Encodes operand `i`, a label or a literal offset, as a PC-relative offset of `len` bits.
+    fn pc_offset(&self, i: usize, len: u32) -> Result<u16, AsmError> {
+        let w = self.word(i)?;
+        let offset = if let Some(&target) = self.symbols.get(w) {
+            target as i32 - (self.address as i32 + 1)
+        } else if let Some(v) = parse_number(w) {
+            v
+        } else {
+            return self.err(format!("undefined label `{}`", w));
+        };
+        let (min, max) = (-(1 << (len - 1)), (1 << (len - 1)) - 1);
+        if offset < min || offset > max {
+            return self.err(format!("`{}` is out of range of a {}-bit PC offset ({} words away)", w, len, offset));
+        }
+        Ok((offset as u16) & ((1 << len) - 1))
+    }
+
+    fn encode(&self) -> Result<Vec<u16>, AsmError> {
+        let op = self.statement.op.as_deref().unwrap();
+        let word = match op {
+            "ADD" | "AND" => {
+                self.expect_operands(3)?;
+                let base = if op == "ADD" { 0b0001 } else { 0b0101 } << 12;
+                let (dr, sr1) = (self.register(0)?, self.register(1)?);
+                match parse_register(self.word(2)?) {
+                    Some(sr2) => base | dr << 9 | sr1 << 6 | sr2,
+                    None => base | dr << 9 | sr1 << 6 | 1 << 5 | self.immediate(2, 5)?,
+                }
+            }
+            "NOT" => {
+                self.expect_operands(2)?;
+                0b1001 << 12 | self.register(0)? << 9 | self.register(1)? << 6 | 0b111111
+            }
+            "JMP" => {
+                self.expect_operands(1)?;
+                0b1100 << 12 | self.register(0)? << 6
+            }
+            "RET" => {
+                self.expect_operands(0)?;
+                0b1100 << 12 | 7 << 6
+            }
+            "JSR" => {
+                self.expect_operands(1)?;
+                0b0100 << 12 | 1 << 11 | self.pc_offset(0, 11)?
+            }
+            "JSRR" => {
+                self.expect_operands(1)?;
+                0b0100 << 12 | self.register(0)? << 6
+            }
+            "LD" | "LDI" | "LEA" | "ST" | "STI" => {
+                self.expect_operands(2)?;
+                let opcode = match op {
+                    "LD" => 0b0010,
+                    "LDI" => 0b1010,
+                    "LEA" => 0b1110,
+                    "ST" => 0b0011,
+                    _ => 0b1011,
+                };
+                opcode << 12 | self.register(0)? << 9 | self.pc_offset(1, 9)?
+            }
+            "LDR" | "STR" => {
+                self.expect_operands(3)?;
+                let opcode = if op == "LDR" { 0b0110 } else { 0b0111 };
+                opcode << 12 | self.register(0)? << 9 | self.register(1)? << 6 | self.immediate(2, 6)?
+            }
+            "TRAP" => {
+                self.expect_operands(1)?;
+                let w = self.word(0)?;
+                match parse_number(w) {
+                    Some(v) if (0..=0xFF).contains(&v) => 0b1111 << 12 | v as u16,
+                    _ => return self.err(format!("invalid trap vector `{}`", w)),
+                }
+            }
+            "RTI" => {
+                self.expect_operands(0)?;
+                0b1000 << 12
+            }
+            "GETC" | "OUT" | "PUTS" | "IN" | "PUTSP" | "HALT" => {
+                self.expect_operands(0)?;
+                let vector = match op {
+                    "GETC" => 0x20,
+                    "OUT" => 0x21,
+                    "PUTS" => 0x22,
+                    "IN" => 0x23,
+                    "PUTSP" => 0x24,
+                    _ => 0x25,
+                };
+                0b1111 << 12 | vector
+            }
+            "NOP" => {
+                self.expect_operands(0)?;
+                0x0000
+            }
+            ".FILL" => {
+                self.expect_operands(1)?;
+                let w = self.word(0)?;
+                if let Some(&address) = self.symbols.get(w) {
+                    address
+                } else {
+                    match parse_number(w) {
+                        Some(v) if (-0x8000..=0xFFFF).contains(&v) => v as u16,
+                        Some(_) => return self.err(format!("value `{}` does not fit in 16 bits", w)),
+                        None => return self.err(format!("undefined label `{}`", w)),
+                    }
+                }
+            }
+            ".BLKW" => {
+                self.expect_operands(1)?;
+                return Ok(vec![0; statement_size(self.statement)? as usize]);
+            }
+            ".STRINGZ" => {
+                self.expect_operands(1)?;
+                let Token::Str(s) = &self.statement.operands[0] else {
+                    return self.err(".STRINGZ requires a string literal");
+                };
+                return Ok(s.chars().map(|c| c as u16).chain(std::iter::once(0)).collect());
+            }
+            branch => {
+                self.expect_operands(1)?;
+                let cc = match &branch[2..] {
+                    "" => 0b111,
+                    flags => flags.chars().fold(0, |acc, c| acc | match c {
+                        'N' => 0b100,
+                        'Z' => 0b010,
+                        _ => 0b001,
+                    }),
+                };
+                cc << 9 | self.pc_offset(0, 9)?
+            }
+        };
+        Ok(vec![word])
+    }
+}
+
+/// Assembles LC-3 assembly source into its segments and symbol table.
+///
+/// The first pass assigns an address to every statement and records the labels; the second encodes
+/// each statement now that every label can be resolved.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut statements = vec![];
+    for (i, text) in source.lines().enumerate() {
+        if let Some(statement) = parse_statement(i + 1, text)? {
+            statements.push(statement);
+        }
+    }
+
+    // First pass: build the symbol table.
+    let mut symbols: Vec<(String, u16)> = vec![];
+    let mut symbol_table: HashMap<String, u16> = HashMap::new();
+    let mut addresses = vec![None; statements.len()];
+    let mut location: Option<u32> = None;
+    for (i, statement) in statements.iter().enumerate() {
+        let line = statement.line;
+        match (statement.op.as_deref(), location) {
+            (Some(".ORIG"), None) => {
+                if statement.label.is_some() {
+                    return error(line, ".ORIG cannot be labelled");
+                }
+                match statement.operands.as_slice() {
+                    [Token::Word(w)] => match parse_number(w) {
+                        Some(v) if (0..=0xFFFF).contains(&v) => location = Some(v as u32),
+                        _ => return error(line, format!("invalid .ORIG address `{}`", w)),
+                    },
+                    _ => return error(line, ".ORIG expects 1 operand"),
+                }
+                continue;
+            }
+            (Some(".ORIG"), Some(_)) => return error(line, ".ORIG inside a block that was not closed with .END"),
+            (_, None) => return error(line, "statement outside of an .ORIG/.END block"),
+            (Some(".END"), Some(_)) => {
+                location = None;
+                continue;
+            }
+            _ => {}
+        }
+        let address = location.unwrap();
+        if address > 0xFFFF {
+            return error(line, "program extends past the end of memory");
+        }
+        if let Some(label) = &statement.label {
+            if symbol_table.insert(label.clone(), address as u16).is_some() {
+                return error(line, format!("label `{}` is defined more than once", label));
+            }
+            symbols.push((label.clone(), address as u16));
+        }
+        addresses[i] = Some(address as u16);
+        location = Some(address + statement_size(statement)? as u32);
+        if location > Some(0x10000) {
+            return error(line, "program extends past the end of memory");
+        }
+    }
+    if location.is_some() {
+        return error(source.lines().count(), "missing .END");
+    }
+    if !statements.iter().any(|s| s.op.as_deref() == Some(".ORIG")) {
+        return error(1, "no .ORIG block, so there is nothing to assemble");
+    }
+
+    // Second pass: encode every statement.
+    let mut segments: Vec<Segment> = vec![];
+    for (statement, address) in statements.iter().zip(addresses) {
+        if statement.op.as_deref() == Some(".ORIG") {
+            let origin = parse_number(match &statement.operands[0] {
+                Token::Word(w) => w,
+                Token::Str(_) => unreachable!(),
+            })
+            .unwrap() as u16;
+            segments.push(Segment { origin, words: vec![], lines: vec![] });
+            continue;
+        }
+        let (Some(address), Some(_)) = (address, &statement.op) else {
+            continue;
+        };
+        let encoder = Encoder { symbols: &symbol_table, statement, address };
+        let words = encoder.encode()?;
+        let segment = segments.last_mut().unwrap();
+        segment.lines.extend(std::iter::repeat_n(statement.line, words.len()));
+        segment.words.extend(words);
+    }
+
+    Ok(Program { segments, symbols })
+}
+
+impl Segment {
+    /// Encodes the segment in the standard `.obj` format: the origin followed by every word, all big-endian.
+    pub fn to_obj_bytes(&self) -> Vec<u8> {
+        std::iter::once(self.origin)
+            .chain(self.words.iter().copied())
+            .flat_map(u16::to_be_bytes)
+            .collect()
+    }
+}
+
+impl Program {
+    /// Renders the symbol table in the `.sym` format written by the standard LC-3 assembler.
+    pub fn symbol_table(&self) -> String {
+        let mut out = String::from("// Symbol table\n// Scope level 0:\n//\tSymbol Name       Page Address\n//\t----------------  ------------\n");
+        for (label, address) in &self.symbols {
+            out.push_str(&format!("//\t{:<16}  {:04X}\n", label, address));
+        }
+        out
+    }
+
+    /// Renders a listing of `source` with the address and encoding of every word next to the line it came from.
+    pub fn listing(&self, source: &str) -> String {
+        let mut words_by_line: HashMap<usize, Vec<(u16, u16)>> = HashMap::new();
+        for segment in &self.segments {
+            for (i, (word, line)) in segment.words.iter().zip(&segment.lines).enumerate() {
+                words_by_line.entry(*line).or_default().push((segment.origin.wrapping_add(i as u16), *word));
+            }
+        }
+        let mut out = String::new();
+        for (i, text) in source.lines().enumerate() {
+            let line = i + 1;
+            match words_by_line.get(&line) {
+                None => out.push_str(&format!("{:30}({:4}) {}\n", "", line, text)),
+                Some(words) => {
+                    for (j, (address, word)) in words.iter().enumerate() {
+                        out.push_str(&format!(
+                            "({:04X}) {:04X}  {:016b} ({:4}) {}\n",
+                            address,
+                            word,
+                            word,
+                            line,
+                            if j == 0 { text } else { "" },
+                        ));
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Assembles `body` at x3000 and returns its words.
+    fn words(body: &str) -> Vec<u16> {
+        let program = assemble(&format!(".ORIG x3000\n{}\n.END\n", body)).unwrap();
+        program.segments.into_iter().flat_map(|s| s.words).collect()
+    }
+
+    fn error_message(source: &str) -> String {
+        match assemble(source) {
+            Ok(_) => panic!("assembled without an error"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn encodes_operate_instructions() {
+        assert_eq!(words("ADD R1, R2, R3"), [0x1283]);
+        assert_eq!(words("ADD R1, R2, #-1"), [0x12BF]);
+        assert_eq!(words("AND R0, R0, #0"), [0x5020]);
+        assert_eq!(words("AND R7, R6, R5"), [0x5F85]);
+        assert_eq!(words("NOT R4, R3"), [0x98FF]);
+    }
+
+    #[test]
+    fn encodes_control_instructions() {
+        assert_eq!(words("JMP R3"), [0xC0C0]);
+        assert_eq!(words("RET"), [0xC1C0]);
+        assert_eq!(words("JSRR R2"), [0x4080]);
+        assert_eq!(words("RTI"), [0x8000]);
+        assert_eq!(words("NOP"), [0x0000]);
+        assert_eq!(words("TRAP x30"), [0xF030]);
+        assert_eq!(words("GETC\nOUT\nPUTS\nIN\nPUTSP\nHALT"), [0xF020, 0xF021, 0xF022, 0xF023, 0xF024, 0xF025]);
+    }
+
+    #[test]
+    fn encodes_pc_relative_offsets_to_labels() {
+        assert_eq!(words("LOOP BRnzp LOOP"), [0x0FFF]);
+        assert_eq!(words("BR NEXT\nNEXT BRz NEXT\nBRnp #3\nBRp x-2"), [0x0E00, 0x05FF, 0x0A03, 0x03FE]);
+        assert_eq!(words("JSR SUB\nSUB RET"), [0x4800, 0xC1C0]);
+        assert_eq!(words("LD R0, V\nLDI R1, V\nLEA R2, V\nST R3, V\nSTI R4, V\nV .FILL #-1"), [
+            0x2004, 0xA203, 0xE402, 0x3601, 0xB800, 0xFFFF,
+        ]);
+    }
+
+    #[test]
+    fn encodes_base_offset_instructions() {
+        assert_eq!(words("LDR R1, R6, #-32"), [0x63A0]);
+        assert_eq!(words("STR R7, R6, #31"), [0x7F9F]);
+    }
+
+    #[test]
+    fn encodes_directives() {
+        assert_eq!(words(".FILL xABCD\n.FILL b101\n.FILL #-2"), [0xABCD, 0x0005, 0xFFFE]);
+        assert_eq!(words(".BLKW #3"), [0, 0, 0]);
+        assert_eq!(words(".STRINGZ \"a\\n\""), [0x61, 0x0A, 0x00]);
+        assert_eq!(words("A .FILL B\nB .FILL A"), [0x3001, 0x3000]);
+    }
+
+    #[test]
+    fn records_segments_and_symbols() {
+        let program = assemble(".ORIG x3000\nSTART ADD R0, R0, #1\n.END\n.ORIG x4000\nDATA .BLKW 2\n.END\n").unwrap();
+        let origins: Vec<u16> = program.segments.iter().map(|s| s.origin).collect();
+        assert_eq!(origins, [0x3000, 0x4000]);
+        assert_eq!(program.segments[1].lines, [5, 5]);
+        assert_eq!(program.symbols, [(String::from("START"), 0x3000), (String::from("DATA"), 0x4000)]);
+    }
+
+    #[test]
+    fn rejects_undefined_labels() {
+        assert_eq!(error_message(".ORIG x3000\nBR NOWHERE\n.END"), "line 2: undefined label `NOWHERE`");
+        assert_eq!(error_message(".ORIG x3000\n.FILL NOWHERE\n.END"), "line 2: undefined label `NOWHERE`");
+    }
+
+    #[test]
+    fn rejects_out_of_range_offsets() {
+        let far = ".ORIG x3000\nLD R0, FAR\n.BLKW #256\nFAR .FILL 0\n.END";
+        assert_eq!(error_message(far), "line 2: `FAR` is out of range of a 9-bit PC offset (256 words away)");
+        assert!(error_message(".ORIG x3000\nADD R0, R0, #16\n.END").contains("does not fit in 5 bits"));
+        assert!(error_message(".ORIG x3000\nLDR R0, R0, #32\n.END").contains("does not fit in 6 bits"));
+        assert!(error_message(".ORIG x3000\nTRAP x100\n.END").contains("invalid trap vector"));
+    }
+
+    #[test]
+    fn rejects_malformed_programs() {
+        assert!(error_message(".ORIG x3000\nA ADD R0, R0, R0\nA NOT R0, R0\n.END").contains("defined more than once"));
+        assert!(error_message(".ORIG x3000\nADD R0, R0\n.END").contains("expects 3 operands, found 2"));
+        assert!(error_message(".ORIG x3000\nADD R8, R0, R0\n.END").contains("expected a register"));
+        assert!(error_message(".ORIG x3000\nHALT").contains("missing .END"));
+        assert!(error_message("HALT").contains("outside of an .ORIG/.END block"));
+        assert!(error_message("; nothing but a comment\n").contains("no .ORIG block"));
+        assert!(error_message(".ORIG x3000\n.STRINGZ \"open\n.END").contains("unterminated string"));
+    }
+}
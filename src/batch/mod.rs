@@ -0,0 +1,151 @@
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+use crate::{
+    lc3::{error::SimError, State},
+    trace::Record,
+};
+
+/// Why a batch run stopped.
+pub enum Outcome {
+    Halted,
+    InstructionLimit,
+    /// The program asked for input after stdin was exhausted.
+    EndOfInput,
+    Error(SimError),
+}
+
+impl Outcome {
+    /// The status code `lasm run` exits with.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Outcome::Halted => 0,
+            Outcome::Error(_) => 1,
+            Outcome::InstructionLimit => 2,
+            Outcome::EndOfInput => 3,
+        }
+    }
+}
+
+/// What a batch run's keyboard input has next.
+pub enum Key {
+    Typed(u8),
+    /// Nothing has been typed yet.
+    NotYet,
+    End,
+}
+
+/// Keyboard input for a batch run.
+pub trait Input {
+    /// Takes the next key, waiting for one to be typed if `wait` is set.
+    fn key(&mut self, wait: bool) -> io::Result<Key>;
+}
+
+impl Input for &[u8] {
+    fn key(&mut self, _wait: bool) -> io::Result<Key> {
+        Ok(match self.split_first() {
+            Some((&c, rest)) => {
+                *self = rest;
+                Key::Typed(c)
+            }
+            None => Key::End,
+        })
+    }
+}
+
+/// Standard input. A terminal is read on a background thread, so that a program can carry on while nothing has
+/// been typed; pipes and files are read as keys are needed, so that runs are repeatable.
+pub struct Stdin {
+    keys: Option<mpsc::Receiver<io::Result<u8>>>,
+}
+
+impl Stdin {
+    pub fn new() -> Self {
+        if !io::stdin().is_terminal() {
+            return Stdin { keys: None };
+        }
+        let (sender, keys) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+        Stdin { keys: Some(keys) }
+    }
+}
+
+impl Input for Stdin {
+    fn key(&mut self, wait: bool) -> io::Result<Key> {
+        let Some(keys) = &self.keys else {
+            let mut c = [0u8];
+            return Ok(if io::stdin().read(&mut c)? == 0 { Key::End } else { Key::Typed(c[0]) });
+        };
+        let key = if wait { keys.recv().map_err(|_| TryRecvError::Disconnected) } else { keys.try_recv() };
+        match key {
+            Ok(byte) => byte.map(Key::Typed),
+            Err(TryRecvError::Empty) => Ok(Key::NotYet),
+            Err(TryRecvError::Disconnected) => Ok(Key::End),
+        }
+    }
+}
+
+/// Runs the program without any user interface until it halts, faults, or has executed `max_instructions`
+/// instructions. Console output goes to `output` and keyboard input is read from `input` one byte at a time,
+/// only when the program asks for it or has keyboard interrupts enabled. If `trace` is given, a [`Record`] of every executed instruction is written to it.
+pub fn run(
+    state: &mut State,
+    max_instructions: Option<u64>,
+    input: &mut impl Input,
+    output: &mut impl Write,
+    mut trace: Option<&mut dyn Write>,
+) -> io::Result<Outcome> {
+    let mut executed = 0u64;
+    let mut end_of_input = false;
+    let outcome = loop {
+        if state.halted() {
+            break Outcome::Halted;
+        }
+        // Keys are waited for when the program is blocked in a TRAP or polling loop. A program with keyboard
+        // interrupts enabled gets keys that have already been typed, but is not held up waiting for one.
+        let wants_input = state.wants_input();
+        if !end_of_input && !state.mem.keyboard.ready() && (wants_input || state.mem.keyboard.interrupt_enable) {
+            match input.key(wants_input)? {
+                Key::Typed(c) => state.mem.keyboard.push(c),
+                Key::NotYet => {}
+                Key::End => end_of_input = true,
+            }
+        }
+        // A program that only has keyboard interrupts enabled can carry on without input, but one that
+        // is blocked in a TRAP or polling loop never will.
+        if end_of_input && !state.mem.keyboard.ready() && (state.waiting_for_input || state.mem.keyboard.polled) {
+            break Outcome::EndOfInput;
+        }
+        if max_instructions.is_some_and(|max| executed >= max) {
+            break Outcome::InstructionLimit;
+        }
+        let result = state.execute_next_instruction();
+        // A TRAP that is waiting for input gets executed again once the input arrives, so it only counts once.
+        if !state.waiting_for_input {
+            executed += 1;
+            if let (Some(trace), Some(record)) = (&mut trace, Record::last(state)) {
+                writeln!(trace, "{}", record)?;
+            }
+        }
+        if let Err(e) = result {
+            break Outcome::Error(e);
+        }
+        if !state.mem.display.output.is_empty() {
+            output.write_all(&state.mem.display.output)?;
+            output.flush()?;
+            state.mem.display.output.clear();
+        }
+    };
+    output.flush()?;
+    if let Some(trace) = trace {
+        trace.flush()?;
+    }
+    Ok(outcome)
+}
@@ -0,0 +1,311 @@
+use crate::{asm::parse_number, lc3::State, symbols::SymbolTable, util::bits};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+/// An expression over the machine state, such as `R0 == x000A` or `mem[x4000] < 0`.
+///
+/// Every value is a 16-bit word, and comparisons treat words as signed. From loosest to tightest binding, the
+/// operators are `||`, `&&`, the comparisons, `&`, then `+` and `-`, then the unary `-` and `!`. Comparisons and
+/// logical operators give 1 for true and 0 for false.
+pub enum Expr {
+    Value(i16),
+    Register(usize),
+    Pc,
+    Psr,
+    Ir,
+    /// One of the condition codes, `N`, `Z` or `P`, as 1 or 0.
+    Cc(u16),
+    Memory(Box<Expr>),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn eval(&self, state: &State) -> i16 {
+        match self {
+            Expr::Value(v) => *v,
+            Expr::Register(r) => state.reg[*r],
+            Expr::Pc => state.pc,
+            Expr::Psr => state.psr,
+            Expr::Ir => state.ir,
+            Expr::Cc(bit) => bits(state.psr, *bit, *bit) as i16,
+            Expr::Memory(address) => state.mem.peek(address.eval(state) as u16),
+            Expr::Negate(e) => e.eval(state).wrapping_neg(),
+            Expr::Not(e) => (e.eval(state) == 0) as i16,
+            Expr::Binary(op, l, r) => {
+                let l = l.eval(state);
+                // Short-circuit, so that `||` and `&&` behave as they read
+                match op {
+                    BinaryOp::Or if l != 0 => return 1,
+                    BinaryOp::And if l == 0 => return 0,
+                    _ => {}
+                }
+                let r = r.eval(state);
+                match op {
+                    BinaryOp::Or | BinaryOp::And => (r != 0) as i16,
+                    BinaryOp::Eq => (l == r) as i16,
+                    BinaryOp::Ne => (l != r) as i16,
+                    BinaryOp::Lt => (l < r) as i16,
+                    BinaryOp::Le => (l <= r) as i16,
+                    BinaryOp::Gt => (l > r) as i16,
+                    BinaryOp::Ge => (l >= r) as i16,
+                    BinaryOp::BitAnd => l & r,
+                    BinaryOp::Add => l.wrapping_add(r),
+                    BinaryOp::Sub => l.wrapping_sub(r),
+                }
+            }
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '#' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                // `#-3` is a single number
+                if !(c.is_ascii_alphanumeric() || c == '_' || c == '#' || (c == '-' && word == "#")) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(word);
+        } else {
+            chars.next();
+            let two = chars.peek().map(|&next| format!("{}{}", c, next));
+            match two.as_deref() {
+                Some("==" | "!=" | "<=" | ">=" | "&&" | "||") => {
+                    tokens.push(two.unwrap());
+                    chars.next();
+                }
+                _ if "<>!&+-()[]".contains(c) => tokens.push(c.to_string()),
+                _ => return Err(format!("unexpected `{}` in expression", c)),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    symbols: &'a SymbolTable,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or_else(|| String::from("unexpected end of expression"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(format!("expected `{}` but found `{}`", expected, token)),
+        }
+    }
+
+    /// Parses a chain of left-associative binary operators at one precedence level.
+    fn binary(
+        &mut self,
+        ops: &[(&str, BinaryOp)],
+        operand: fn(&mut Self) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        let mut left = operand(self)?;
+        while let Some(&(_, op)) = ops.iter().find(|(token, _)| self.peek() == Some(token)) {
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(operand(self)?));
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        self.binary(&[("||", BinaryOp::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.binary(&[("&&", BinaryOp::And)], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let ops = [
+            ("==", BinaryOp::Eq),
+            ("!=", BinaryOp::Ne),
+            ("<=", BinaryOp::Le),
+            (">=", BinaryOp::Ge),
+            ("<", BinaryOp::Lt),
+            (">", BinaryOp::Gt),
+        ];
+        self.binary(&ops, Self::bit_and)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, String> {
+        self.binary(&[("&", BinaryOp::BitAnd)], Self::sum)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        self.binary(&[("+", BinaryOp::Add), ("-", BinaryOp::Sub)], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some("-") => {
+                self.pos += 1;
+                Ok(Expr::Negate(Box::new(self.unary()?)))
+            }
+            Some("!") => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.next()?;
+        if token == "(" {
+            let e = self.or()?;
+            self.expect(")")?;
+            return Ok(e);
+        }
+        if let Some(v) = parse_number(&token) {
+            if !(-0x8000..=0xFFFF).contains(&v) {
+                return Err(format!("`{}` does not fit in 16 bits", token));
+            }
+            return Ok(Expr::Value(v as u16 as i16));
+        }
+        match token.to_ascii_uppercase().as_str() {
+            "PC" => return Ok(Expr::Pc),
+            "PSR" => return Ok(Expr::Psr),
+            "IR" => return Ok(Expr::Ir),
+            "N" => return Ok(Expr::Cc(2)),
+            "Z" => return Ok(Expr::Cc(1)),
+            "P" => return Ok(Expr::Cc(0)),
+            "MEM" => {
+                self.expect("[")?;
+                let address = self.or()?;
+                self.expect("]")?;
+                return Ok(Expr::Memory(Box::new(address)));
+            }
+            _ => {}
+        }
+        if let Some(Ok(r)) = token.strip_prefix(['R', 'r']).map(str::parse::<usize>) {
+            if r < 8 {
+                return Ok(Expr::Register(r));
+            }
+        }
+        match self.symbols.address(&token) {
+            Some(address) => Ok(Expr::Value(address as i16)),
+            None => Err(format!("unknown name `{}` in expression", token)),
+        }
+    }
+}
+
+/// Parses an expression, in which labels from `symbols` stand for their addresses.
+pub fn parse(input: &str, symbols: &SymbolTable) -> Result<Expr, String> {
+    let mut parser = Parser { tokens: tokenize(input)?, pos: 0, symbols };
+    let e = parser.or()?;
+    match parser.peek() {
+        None => Ok(e),
+        Some(token) => Err(format!("unexpected `{}` in expression", token)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> State<'static> {
+        let mut mem = [0; 65536];
+        mem[0x4000] = 7;
+        mem[0x4001] = -1;
+        let mut state = State::new("test", mem);
+        state.reg[0] = 10;
+        state.reg[1] = 0x4000;
+        state.pc = 0x3005;
+        state.psr = 0x8002u16 as i16;
+        state
+    }
+
+    fn eval(input: &str) -> i16 {
+        let mut symbols = SymbolTable::default();
+        symbols.insert("DATA", 0x4001);
+        match parse(input, &symbols) {
+            Ok(e) => e.eval(&state()),
+            Err(e) => panic!("{}: {}", input, e),
+        }
+    }
+
+    fn error(input: &str) -> String {
+        parse(input, &SymbolTable::default()).err().unwrap()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 - 4"), -1);
+        assert_eq!(eval("5 - (2 - 1)"), 4);
+        assert_eq!(eval("x7 & 2 + 1"), 3);
+        assert_eq!(eval("1 + 1 == 2 && 0 || 3 > 2"), 1);
+        assert_eq!(eval("- -1"), 1);
+        assert_eq!(eval("!0 + !5"), 1);
+    }
+
+    #[test]
+    fn comparisons_are_signed() {
+        assert_eq!(eval("xFFFF < 0"), 1);
+        assert_eq!(eval("#-3 <= #-3"), 1);
+        assert_eq!(eval("x8000 > x7FFF"), 0);
+        assert_eq!(eval("1 != 1"), 0);
+        assert_eq!(eval("2 >= 3"), 0);
+    }
+
+    #[test]
+    fn machine_state() {
+        assert_eq!(eval("R0 == x000A"), 1);
+        assert_eq!(eval("r1"), 0x4000);
+        assert_eq!(eval("mem[R1]"), 7);
+        assert_eq!(eval("mem[R1 + 1] < 0"), 1);
+        assert_eq!(eval("MEM[DATA]"), -1);
+        assert_eq!(eval("DATA"), 0x4001);
+        assert_eq!(eval("pc"), 0x3005);
+        assert_eq!(eval("PSR & x8000"), 0x8000u16 as i16);
+        assert_eq!((eval("N"), eval("Z"), eval("P")), (0, 1, 0));
+        assert_eq!(eval("IR"), 0);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("R0 =="), "unexpected end of expression");
+        assert_eq!(error("(R0"), "unexpected end of expression");
+        assert_eq!(error("mem(R0)"), "expected `[` but found `(`");
+        assert_eq!(error("R0 R1"), "unexpected `R1` in expression");
+        assert_eq!(error("R0 * 2"), "unexpected `*` in expression");
+        assert_eq!(error("R8"), "unknown name `R8` in expression");
+        assert_eq!(error("LOOP"), "unknown name `LOOP` in expression");
+        assert_eq!(error("x10000"), "`x10000` does not fit in 16 bits");
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::lc3::{calls::Frame, error::SimError, memory::undoable, State};
+use expr::Expr;
+
+pub mod expr;
+
+/// Why a run stopped before executing every instruction it was allowed to.
+pub enum Stop {
+    Breakpoint,
+    /// A watch was triggered, for the reason given.
+    Watch(String),
+    /// The call being stepped over or out of returned.
+    Returned(Frame),
+    Halted,
+    Error(SimError),
+}
+
+/// Something an instruction can do that stops a run.
+pub enum Watch {
+    /// The word at `address` is read or written (or either, if both are set).
+    Memory { address: u16, read: bool, write: bool },
+    /// A register changes, or changes to `value` if one is given.
+    Register { register: usize, value: Option<i16> },
+}
+
+impl Watch {
+    /// Describes what the instruction `state` just executed did to trigger this watch, if it did. `before` holds
+    /// the registers from before it executed.
+    fn triggered(&self, before: &[i16; 8], state: &State) -> Option<String> {
+        match *self {
+            Watch::Memory { address, read, write } => {
+                if write {
+                    if let Some(&(_, old, written)) = state.mem.writes.iter().find(|&&(a, _, _)| a == address) {
+                        // What a device register like DDR held before is meaningless, so only the write is shown
+                        if !undoable(address) {
+                            return Some(format!("x{:0>4X} written with x{:0>4X}", address, written));
+                        }
+                        let new = state.mem.peek(address);
+                        return Some(format!("x{:0>4X} written (x{:0>4X} -> x{:0>4X})", address, old, new));
+                    }
+                }
+                if read && state.mem.reads.contains(&address) {
+                    return Some(format!("x{:0>4X} read", address));
+                }
+                None
+            }
+            Watch::Register { register, value } => {
+                let (old, new) = (before[register], state.reg[register]);
+                if old == new || value.is_some_and(|v| v != new) {
+                    return None;
+                }
+                Some(format!("R{} changed (x{:0>4X} -> x{:0>4X})", register, old, new))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Watch::Memory { address, read, write } => {
+                let access = match (read, write) {
+                    (true, true) => "read/write",
+                    (true, false) => "read",
+                    _ => "write",
+                };
+                write!(f, "x{:0>4X} {}", address, access)
+            }
+            Watch::Register { register, value: Some(value) } => write!(f, "R{} = x{:0>4X}", register, value),
+            Watch::Register { register, value: None } => write!(f, "R{} changes", register),
+        }
+    }
+}
+
+/// What stops a run at a breakpoint's address.
+#[derive(Default)]
+pub struct Breakpoint {
+    /// Only stop when this holds, kept along with the text it was parsed from.
+    pub condition: Option<(String, Expr)>,
+    /// How many more hits to carry on through before stopping.
+    pub ignore: u32,
+    /// How many times the PC has reached the breakpoint with its condition holding.
+    pub hits: u32,
+}
+
+impl Breakpoint {
+    /// Counts a hit if the condition holds, and returns whether the run should stop.
+    fn hit(&mut self, state: &State) -> bool {
+        if !self.holds(state) {
+            return false;
+        }
+        self.hits += 1;
+        if self.ignore > 0 {
+            self.ignore -= 1;
+            return false;
+        }
+        true
+    }
+
+    fn holds(&self, state: &State) -> bool {
+        self.condition.as_ref().is_none_or(|(_, condition)| condition.eval(state) != 0)
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((text, _)) = &self.condition {
+            write!(f, "if {} ", text)?;
+        }
+        if self.ignore > 0 {
+            write!(f, "ignore {} ", self.ignore)?;
+        }
+        write!(f, "({} hit{})", self.hits, if self.hits == 1 { "" } else { "s" })
+    }
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    pub breakpoints: BTreeMap<u16, Breakpoint>,
+    pub watches: Vec<Watch>,
+    /// The call being stepped over or out of, and the call depth from before it was made. Runs stop once the
+    /// program is back at that depth.
+    pub returning: Option<(usize, Frame)>,
+}
+
+impl Debugger {
+    /// Sets a breakpoint at `address`, or clears it if one is already set. Returns whether a breakpoint is now set.
+    pub fn toggle_breakpoint(&mut self, address: u16) -> bool {
+        if self.breakpoints.remove(&address).is_some() {
+            false
+        } else {
+            self.breakpoints.insert(address, Breakpoint::default());
+            true
+        }
+    }
+
+    /// Executes at most `limit` instructions, stopping early when the PC reaches a breakpoint, a watch is
+    /// triggered, the machine halts, or an instruction fails. The first instruction is always executed, so that a
+    /// run can be continued from a breakpoint.
+    ///
+    /// Returns `None` if no stop condition was met, including when the program is waiting for input. A call being
+    /// stepped over or out of is forgotten once the run stops for any reason.
+    pub fn run(&mut self, state: &mut State, limit: usize) -> Option<Stop> {
+        let stop = self.run_until_stop(state, limit);
+        if stop.is_some() {
+            self.returning = None;
+        }
+        stop
+    }
+
+    fn run_until_stop(&mut self, state: &mut State, limit: usize) -> Option<Stop> {
+        for _ in 0..limit {
+            if state.halted() {
+                return Some(Stop::Halted);
+            }
+            if state.waiting_for_input && !state.mem.keyboard.ready() {
+                return None;
+            }
+            let before = state.reg;
+            if let Err(e) = state.execute_next_instruction() {
+                return Some(Stop::Error(e));
+            }
+            if state.waiting_for_input {
+                continue;
+            }
+            if let Some(reason) = self.watches.iter().find_map(|w| w.triggered(&before, state)) {
+                return Some(Stop::Watch(reason));
+            }
+            if self.count_hit(state) {
+                return Some(Stop::Breakpoint);
+            }
+            if let Some((depth, frame)) = self.returning {
+                if state.call_depth() <= depth {
+                    return Some(Stop::Returned(frame));
+                }
+            }
+        }
+        if state.halted() {
+            return Some(Stop::Halted);
+        }
+        None
+    }
+
+    /// Counts a hit on the breakpoint the PC has reached, if there is one, and returns whether a run should stop
+    /// there.
+    fn count_hit(&mut self, state: &State) -> bool {
+        !state.waiting_for_input && self.breakpoints.get_mut(&(state.pc as u16)).is_some_and(|b| b.hit(state))
+    }
+
+    /// Executes the next instruction. Reaching a breakpoint this way counts as a hit, and uses up one of the
+    /// hits it ignores, just as in a run.
+    pub fn step(&mut self, state: &mut State) -> Result<(), SimError> {
+        state.execute_next_instruction()?;
+        self.count_hit(state);
+        Ok(())
+    }
+
+    /// Executes the next instruction, and if it makes a call, arranges for runs to carry on until the call
+    /// returns. Returns whether a run is needed to finish the step.
+    pub fn step_over(&mut self, state: &mut State) -> Result<bool, SimError> {
+        let depth = state.call_depth();
+        self.step(state)?;
+        self.returning = match state.calls.last() {
+            Some(&frame) if state.call_depth() > depth => Some((depth, frame)),
+            _ => None,
+        };
+        Ok(self.returning.is_some())
+    }
+
+    /// Arranges for runs to carry on until the innermost call returns. Returns false if there is no call to
+    /// step out of.
+    pub fn step_out(&mut self, state: &State) -> bool {
+        self.returning = state.calls.last().map(|&frame| (state.call_depth() - 1, frame));
+        self.returning.is_some()
+    }
+
+    /// Steps backwards until the PC reaches a breakpoint whose condition holds, always undoing at least one
+    /// instruction. Hit counts are left alone. Returns false if the start of the journal was reached first.
+    pub fn run_back(&self, state: &mut State) -> bool {
+        while state.step_back() {
+            if self.breakpoints.get(&(state.pc as u16)).is_some_and(|b| b.holds(state)) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lc3::calls::{FrameKind, CALL_STACK_LIMIT};
+
+    /// A machine running `words` from x3000.
+    fn machine(words: &[u16]) -> State<'static> {
+        let mut mem = [0; 65536];
+        for (i, &word) in words.iter().enumerate() {
+            mem[0x3000 + i] = word as i16;
+        }
+        State::new("test", mem)
+    }
+
+    /// Fills the call stack with calls from elsewhere.
+    fn fill_call_stack(state: &mut State) {
+        let outer = Frame { site: 0x2000, target: 0x2000, kind: FrameKind::Subroutine };
+        state.calls = vec![outer; CALL_STACK_LIMIT];
+    }
+
+    #[test]
+    fn steps_count_breakpoint_hits() {
+        // LOOP ADD R0, R0, #1; BRnzp LOOP
+        let mut state = machine(&[0x1021, 0x0FFE]);
+        let mut debugger = Debugger::default();
+        debugger.breakpoints.insert(0x3001, Breakpoint { ignore: 2, ..Breakpoint::default() });
+
+        // The first hit is stepped to, and the second is run through, so the run stops at the third
+        debugger.step(&mut state).unwrap();
+        assert_eq!(debugger.breakpoints[&0x3001].hits, 1);
+        assert!(matches!(debugger.run(&mut state, 100), Some(Stop::Breakpoint)));
+        assert_eq!(state.reg[0], 0x8888u16 as i16 + 3);
+        assert_eq!(debugger.breakpoints[&0x3001].hits, 3);
+        assert_eq!(debugger.breakpoints[&0x3001].ignore, 0);
+    }
+
+    #[test]
+    fn step_over_a_call_with_the_call_stack_full() {
+        // JSR SUB; HALT; SUB ADD R0, R0, #1; RET
+        let mut state = machine(&[0x4801, 0xF025, 0x1021, 0xC1C0]);
+        fill_call_stack(&mut state);
+        let mut debugger = Debugger::default();
+        assert!(debugger.step_over(&mut state).unwrap());
+        assert!(matches!(debugger.run(&mut state, 100), Some(Stop::Returned(frame)) if frame.target == 0x3002));
+        assert_eq!(state.pc, 0x3001);
+    }
+
+    #[test]
+    fn step_out_of_a_call_with_the_call_stack_full() {
+        // JSR SUB; HALT
+        // SUB ADD R1, R7, #0; JSR INNER; ADD R7, R1, #0; RET
+        // INNER RET
+        let mut state = machine(&[0x4801, 0xF025, 0x13E0, 0x4802, 0x1E60, 0xC1C0, 0xC1C0]);
+        fill_call_stack(&mut state);
+        let mut debugger = Debugger::default();
+        state.execute_next_instruction().unwrap();
+        assert!(debugger.step_out(&state));
+        // Returning from INNER leaves the stack shorter than it was in SUB, since calls were dropped from it
+        assert!(matches!(debugger.run(&mut state, 100), Some(Stop::Returned(frame)) if frame.target == 0x3002));
+        assert_eq!(state.pc, 0x3001);
+        assert_eq!(state.call_depth(), CALL_STACK_LIMIT);
+    }
+}
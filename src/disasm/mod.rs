@@ -0,0 +1,136 @@
+use crate::{
+    symbols::SymbolTable,
+    util::{bits, sext, unsext},
+};
+
+pub mod program;
+
+fn reg(n: u16) -> String {
+    format!("R{}", n)
+}
+
+fn imm(val: i16) -> String {
+    format!("#{}", val)
+}
+
+/// A PC-relative operand, written as the label it points at if there is one. Otherwise it is written as the address
+/// it points at, or as the offset itself in `source` mode, since that is how the assembler reads a number.
+fn target(address: u16, offset: i16, symbols: &SymbolTable, source: bool) -> String {
+    let target = address.wrapping_add(1).wrapping_add(offset as u16);
+    match symbols.label(target) {
+        Some(label) => label.to_string(),
+        None if source => format!("#{}", offset),
+        None => format!("x{:0>4X}", target),
+    }
+}
+
+/// Returns the assembly for the word stored at `address`, with PC-relative operands resolved to the absolute
+/// address they refer to. Words that are not valid instructions come back as a `.FILL`.
+pub fn disassemble(word: i16, address: u16) -> String {
+    disassemble_with_symbols(word, address, &SymbolTable::default())
+}
+
+/// Like [`disassemble`], but PC-relative operands that point at a label are written as the label.
+pub fn disassemble_with_symbols(word: i16, address: u16, symbols: &SymbolTable) -> String {
+    decode(word, address, symbols, false)
+}
+
+/// Like [`disassemble_with_symbols`], but written so that assembling the result gives back `word` exactly.
+pub fn disassemble_as_source(word: i16, address: u16, symbols: &SymbolTable) -> String {
+    decode(word, address, symbols, true)
+}
+
+fn decode(word: i16, address: u16, symbols: &SymbolTable, source: bool) -> String {
+    let fill = format!(".FILL x{:0>4X}", unsext(word));
+    let dr = bits(word, 11, 9);
+    let sr1 = bits(word, 8, 6);
+    match bits(word, 15, 12) {
+        0b0000 => {
+            let n = bits(word, 11, 11) == 1;
+            let z = bits(word, 10, 10) == 1;
+            let p = bits(word, 9, 9) == 1;
+            if !(n || z || p) {
+                // A never-taken branch with an offset has no assembly form of its own.
+                if source && word != 0 {
+                    return fill;
+                }
+                return String::from("NOP");
+            }
+            let cc = if n && z && p {
+                String::new()
+            } else {
+                format!("{}{}{}", if n { "n" } else { "" }, if z { "z" } else { "" }, if p { "p" } else { "" })
+            };
+            format!("BR{} {}", cc, target(address, sext(bits(word, 8, 0), 9), symbols, source))
+        }
+        op @ (0b0001 | 0b0101) => {
+            let name = if op == 0b0001 { "ADD" } else { "AND" };
+            if bits(word, 5, 5) == 1 {
+                format!("{} {}, {}, {}", name, reg(dr), reg(sr1), imm(sext(bits(word, 4, 0), 5)))
+            } else if bits(word, 4, 3) == 0 {
+                format!("{} {}, {}, {}", name, reg(dr), reg(sr1), reg(bits(word, 2, 0)))
+            } else {
+                fill
+            }
+        }
+        0b1001 => {
+            if bits(word, 5, 0) != 0b111111 {
+                return fill;
+            }
+            format!("NOT {}, {}", reg(dr), reg(sr1))
+        }
+        0b1100 => {
+            if dr != 0 || bits(word, 5, 0) != 0 {
+                fill
+            } else if sr1 == 7 {
+                String::from("RET")
+            } else {
+                format!("JMP {}", reg(sr1))
+            }
+        }
+        0b0100 => {
+            if bits(word, 11, 11) == 1 {
+                format!("JSR {}", target(address, sext(bits(word, 10, 0), 11), symbols, source))
+            } else if bits(word, 10, 9) != 0 || bits(word, 5, 0) != 0 {
+                fill
+            } else {
+                format!("JSRR {}", reg(sr1))
+            }
+        }
+        op @ (0b0010 | 0b1010 | 0b1110 | 0b0011 | 0b1011) => {
+            let name = match op {
+                0b0010 => "LD",
+                0b1010 => "LDI",
+                0b1110 => "LEA",
+                0b0011 => "ST",
+                _ => "STI",
+            };
+            format!("{} {}, {}", name, reg(dr), target(address, sext(bits(word, 8, 0), 9), symbols, source))
+        }
+        op @ (0b0110 | 0b0111) => {
+            let name = if op == 0b0110 { "LDR" } else { "STR" };
+            format!("{} {}, {}, {}", name, reg(dr), reg(sr1), imm(sext(bits(word, 5, 0), 6)))
+        }
+        0b1111 => {
+            if bits(word, 11, 8) != 0 {
+                return fill;
+            }
+            match bits(word, 7, 0) {
+                0x20 => String::from("GETC"),
+                0x21 => String::from("OUT"),
+                0x22 => String::from("PUTS"),
+                0x23 => String::from("IN"),
+                0x24 => String::from("PUTSP"),
+                0x25 => String::from("HALT"),
+                vector => format!("TRAP x{:0>2X}", vector),
+            }
+        }
+        0b1000 => {
+            if bits(word, 11, 0) != 0 {
+                return fill;
+            }
+            String::from("RTI")
+        }
+        _ => fill,
+    }
+}
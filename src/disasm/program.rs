@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::disassemble_as_source;
+use crate::{
+    lc3::interrupt::INTERRUPT_VECTOR_TABLE,
+    loader::Segment,
+    symbols::SymbolTable,
+    util::{bits, sext},
+};
+
+/// Words below this address are in the trap or interrupt vector table, so they hold the addresses of handlers
+/// rather than code.
+const VECTOR_TABLES_END: u16 = INTERRUPT_VECTOR_TABLE + 0x100;
+
+/// Where control can go after executing `word`, and which address it refers to, if any.
+struct Flow {
+    falls_through: bool,
+    target: Option<u16>,
+    /// Whether `target` is a place control goes, as opposed to data the instruction loads, stores or takes the
+    /// address of.
+    jumps: bool,
+}
+
+fn flow(word: i16, address: u16) -> Flow {
+    let next = address.wrapping_add(1);
+    let pc_offset = |len| Some(next.wrapping_add(sext(bits(word, len - 1, 0), len) as u16));
+    match bits(word, 15, 12) {
+        0b0000 => {
+            let nzp = bits(word, 11, 9);
+            Flow { falls_through: nzp != 0b111, target: if nzp == 0 { None } else { pc_offset(9) }, jumps: true }
+        }
+        0b0100 if bits(word, 11, 11) == 1 => Flow { falls_through: true, target: pc_offset(11), jumps: true },
+        0b0010 | 0b1010 | 0b1110 | 0b0011 | 0b1011 => Flow { falls_through: true, target: pc_offset(9), jumps: false },
+        // JMP, RET and RTI go somewhere only known at run time, and HALT goes nowhere.
+        0b1100 | 0b1000 => Flow { falls_through: false, target: None, jumps: false },
+        0b1111 if bits(word, 7, 0) == 0x25 => Flow { falls_through: false, target: None, jumps: false },
+        _ => Flow { falls_through: true, target: None, jumps: false },
+    }
+}
+
+/// Whether a word can be written inside a `.STRINGZ`.
+fn is_text(word: i16) -> bool {
+    matches!(word, 0x20..=0x7E | 0x09 | 0x0A | 0x0D | 0x1B)
+}
+
+fn escape(word: i16) -> String {
+    match word as u8 {
+        b'\n' => String::from("\\n"),
+        b'\t' => String::from("\\t"),
+        b'\r' => String::from("\\r"),
+        0x1B => String::from("\\e"),
+        b'"' => String::from("\\\""),
+        b'\\' => String::from("\\\\"),
+        c => (c as char).to_string(),
+    }
+}
+
+/// Turns loaded segments back into assembly source that assembles to the same words.
+///
+/// Code is found by following control flow from the start of every segment, and from every handler in a vector
+/// table: branch and `JSR` targets and fall-through successors are code, and everything else is data. Every address
+/// an instruction or vector table refers to gets a label, named after how it is used (`HANDLER_` for trap and
+/// interrupt handlers, `SUB_` for subroutines, `L_` for branch targets and `DATA_` for the rest).
+/// Data is written as `.STRINGZ` where it looks like a zero-terminated string, `.BLKW` for runs of zeros and
+/// `.FILL` otherwise.
+pub fn disassemble_program(segments: &[Segment]) -> String {
+    let mut words = BTreeMap::new();
+    for segment in segments {
+        for (i, &word) in segment.words.iter().enumerate() {
+            words.insert(segment.origin.wrapping_add(i as u16), word);
+        }
+    }
+
+    // Find the code. Words that are not valid instructions end a path, since execution would fault there.
+    let mut code = BTreeSet::new();
+    let handlers: Vec<u16> = words
+        .range(..VECTOR_TABLES_END)
+        .map(|(_, &w)| w as u16)
+        .filter(|w| words.contains_key(w))
+        .collect();
+    let mut pending: Vec<u16> = segments.iter().map(|s| s.origin).filter(|&o| o >= VECTOR_TABLES_END).collect();
+    pending.extend(&handlers);
+    while let Some(address) = pending.pop() {
+        let Some(&word) = words.get(&address) else {
+            continue;
+        };
+        if code.contains(&address) || disassemble_as_source(word, address, &SymbolTable::default()).starts_with(".FILL") {
+            continue;
+        }
+        code.insert(address);
+        let flow = flow(word, address);
+        if flow.falls_through {
+            pending.push(address.wrapping_add(1));
+        }
+        if let (Some(target), true) = (flow.target, flow.jumps) {
+            pending.push(target);
+        }
+    }
+
+    // Label everything the code refers to, preferring the most descriptive name for addresses used several ways.
+    let mut labels: BTreeMap<u16, &str> = handlers.iter().map(|&h| (h, "HANDLER")).collect();
+    for &address in &code {
+        let word = words[&address];
+        let flow = flow(word, address);
+        let Some(target) = flow.target.filter(|t| words.contains_key(t)) else {
+            continue;
+        };
+        let kind = match (flow.jumps, bits(word, 15, 12)) {
+            (true, 0b0100) => "SUB",
+            (true, _) => "L",
+            (false, _) => "DATA",
+        };
+        let rank = |kind| ["DATA", "L", "SUB", "HANDLER"].iter().position(|&k| k == kind);
+        if labels.get(&target).is_none_or(|&existing| rank(existing) < rank(kind)) {
+            labels.insert(target, kind);
+        }
+    }
+    let mut symbols = SymbolTable::default();
+    for (&address, kind) in &labels {
+        symbols.insert(&format!("{}_{:0>4X}", kind, address), address);
+    }
+
+    let mut out = String::new();
+    for segment in segments {
+        out.push_str(&format!("{:<12} .ORIG x{:0>4X}\n", "", segment.origin));
+        let end = segment.origin as usize + segment.words.len();
+        let mut address = segment.origin as usize;
+        while address < end {
+            let a = address as u16;
+            let word = words[&a];
+            let label = symbols.label(a).unwrap_or_default();
+            // Runs of data can be written as a single directive, as long as nothing refers to their middle.
+            let free = |i: usize| i < end && !code.contains(&(i as u16)) && (i == address || symbols.label(i as u16).is_none());
+            let run = |part_of_run: fn(i16) -> bool| (address..).take_while(|&i| free(i) && part_of_run(words[&(i as u16)])).count();
+            let text_len = run(is_text);
+            let zeros = run(|w| w == 0);
+            let (text, len) = if code.contains(&a) {
+                (disassemble_as_source(word, a, &symbols), 1)
+            } else if text_len >= 2 && free(address + text_len) && words[&((address + text_len) as u16)] == 0 {
+                let text: String = (address..address + text_len).map(|i| escape(words[&(i as u16)])).collect();
+                (format!(".STRINGZ \"{}\"", text), text_len + 1)
+            } else if let Some(handler) = symbols.label(word as u16).filter(|_| a < VECTOR_TABLES_END) {
+                (format!(".FILL {}", handler), 1)
+            } else if zeros >= 2 {
+                (format!(".BLKW #{}", zeros), zeros)
+            } else {
+                (format!(".FILL x{:0>4X}", word as u16), 1)
+            };
+            out.push_str(&format!("{:<12} {}\n", label, text));
+            address += len;
+        }
+        out.push_str(&format!("{:<12} .END\n", ""));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    /// Disassembles what `source` assembles to, and checks that the result assembles to the same segments.
+    fn round_trip(source: &str) -> String {
+        let to_segments = |source: &str| -> Vec<Segment> {
+            let program = assemble(source).unwrap_or_else(|e| panic!("{}\n{}", e, source));
+            program
+                .segments
+                .iter()
+                .map(|s| Segment { origin: s.origin, words: s.words.iter().map(|&w| w as i16).collect() })
+                .collect()
+        };
+        let segments = to_segments(source);
+        let disassembled = disassemble_program(&segments);
+        let again = to_segments(&disassembled);
+        assert_eq!(segments.len(), again.len());
+        for (s, a) in segments.iter().zip(&again) {
+            assert_eq!((s.origin, &s.words), (a.origin, &a.words), "{}", disassembled);
+        }
+        disassembled
+    }
+
+    #[test]
+    fn programs_round_trip() {
+        let disassembled = round_trip(
+            "        .ORIG x3000
+                     LEA R0, MSG
+                     PUTS
+                     AND R1, R1, #0
+                     ADD R1, R1, #5
+             LOOP    JSR SHOW
+                     ADD R1, R1, #-1
+                     BRp LOOP
+                     LD R2, COUNT
+                     STI R2, PTR
+                     HALT
+             SHOW    ST R7, SAVE
+                     LDR R3, R6, #-2
+                     NOT R3, R3
+                     LD R7, SAVE
+                     RET
+             MSG     .STRINGZ \"Hi\\n\"
+             SAVE    .BLKW 3
+             COUNT   .FILL #-7
+             PTR     .FILL x4000
+                     .END",
+        );
+        assert!(disassembled.contains("JSR SUB_300A"));
+        assert!(disassembled.contains("BRp L_3004"));
+        assert!(disassembled.contains(".STRINGZ \"Hi\\n\""));
+        assert!(disassembled.contains(".BLKW #3"));
+    }
+
+    #[test]
+    fn handlers_are_found_from_vector_table_entries_only() {
+        // The vector table entry and the handler share a segment, whose code is only reachable from the entry.
+        let disassembled = round_trip(
+            "        .ORIG x01FF
+                     .FILL KBD
+             KBD     LDI R0, KBDR
+                     RTI
+             KBDR    .FILL xFE02
+                     .FILL KBDR
+                     .END",
+        );
+        assert!(disassembled.contains(".FILL HANDLER_0200"));
+        assert!(disassembled.contains("HANDLER_0200 LDI R0, DATA_0202"));
+        assert!(!disassembled.contains("HANDLER_0202"));
+    }
+}
@@ -0,0 +1,75 @@
+use super::State;
+
+/// How many calls deep the call stack goes. Beyond this the outermost calls are forgotten, so that a program which
+/// keeps calling without returning, like a loop that jumps back out of its subroutine, does not grow it forever.
+pub const CALL_STACK_LIMIT: usize = 1024;
+
+/// How a frame was entered, which decides how it returns.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    /// `JSR` or `JSRR`, returned from with `RET`.
+    Subroutine,
+    /// `TRAP` with this vector, returned from with `RTI` (or `RET`, through the return address in R7).
+    Trap(u16),
+    /// An interrupt or exception with this vector, returned from with `RTI`.
+    Interrupt(u16),
+}
+
+/// A subroutine call, trap routine or interrupt handler that has not returned yet.
+#[derive(Clone, Copy)]
+pub struct Frame {
+    /// The address of the `JSR`, `JSRR` or `TRAP`, or for an interrupt or exception, the address the interrupted
+    /// program resumes at.
+    pub site: u16,
+    /// The address of the subroutine or handler.
+    pub target: u16,
+    pub kind: FrameKind,
+}
+
+impl Frame {
+    /// Where control goes when the call returns.
+    pub fn return_address(&self) -> u16 {
+        match self.kind {
+            FrameKind::Interrupt(_) => self.site,
+            FrameKind::Subroutine | FrameKind::Trap(_) => self.site.wrapping_add(1),
+        }
+    }
+}
+
+impl State<'_> {
+    /// How many calls deep the program is, counting calls dropped from the call stack. Unlike the length of
+    /// `calls`, this only goes up on a call and down on a return, so it tells whether a given call has returned.
+    pub fn call_depth(&self) -> usize {
+        self.calls_dropped + self.calls.len()
+    }
+
+    /// Forgets every call in progress, e.g. after the PC is changed by hand.
+    pub fn clear_calls(&mut self) {
+        self.calls.clear();
+        self.calls_dropped = 0;
+    }
+
+    pub(super) fn enter(&mut self, frame: Frame) {
+        let dropped = (self.calls.len() == CALL_STACK_LIMIT).then(|| self.calls.remove(0));
+        self.calls_dropped += dropped.is_some() as usize;
+        self.calls.push(frame);
+        self.journal.record_call(dropped);
+    }
+
+    /// Pops the frame that returning to the PC with `RET` (or `RTI`, if `rti` is set) finishes, along with any
+    /// frames above it that were left without returning. Jumps that do not match a frame are not returns. Trap
+    /// routines can return either way, since `TRAP` saves the return address in R7 as well as on the stack.
+    pub(super) fn leave(&mut self, rti: bool) {
+        let pc = self.pc as u16;
+        let returns = |kind| match kind {
+            FrameKind::Subroutine => !rti,
+            FrameKind::Trap(_) => true,
+            FrameKind::Interrupt(_) => rti,
+        };
+        let frame = self.calls.iter().rposition(|f| returns(f.kind) && f.return_address() == pc);
+        if let Some(i) = frame {
+            let returned = self.calls.split_off(i);
+            self.journal.record_returns(returned);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+
+use super::interrupt;
+
+/// What went wrong while executing an instruction.
+#[derive(Debug, Clone)]
+pub enum SimErrorKind {
+    /// The instruction has bits set that its format requires to be zero.
+    MalformedInstruction(&'static str),
+    /// User mode code tried to access system space or the device registers.
+    AccessControlViolation { address: u16 },
+    /// `RTI` was executed in user mode.
+    PrivilegeModeViolation,
+    /// The reserved opcode 1101 was executed.
+    IllegalOpcode,
+    /// A device requested an interrupt. Its interrupts are disabled when this is reported, since there is no
+    /// handler to take them.
+    Interrupt { vector: u16 },
+}
+
+impl SimErrorKind {
+    /// The interrupt vector the machine transfers control through, for the kinds that are exceptions or
+    /// interrupts rather than simulator errors. These only become errors when no handler is installed.
+    pub fn vector(&self) -> Option<u16> {
+        match self {
+            SimErrorKind::MalformedInstruction(_) => None,
+            SimErrorKind::AccessControlViolation { .. } => Some(interrupt::ACCESS_CONTROL_VIOLATION),
+            SimErrorKind::PrivilegeModeViolation => Some(interrupt::PRIVILEGE_MODE_VIOLATION),
+            SimErrorKind::IllegalOpcode => Some(interrupt::ILLEGAL_OPCODE),
+            SimErrorKind::Interrupt { vector } => Some(*vector),
+        }
+    }
+}
+
+/// An error that stops the simulator, along with where it happened.
+#[derive(Debug, Clone)]
+pub struct SimError {
+    /// Address of the faulting instruction, or for an interrupt, of the instruction it arrived before.
+    pub pc: u16,
+    pub ir: i16,
+    pub kind: SimErrorKind,
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error at x{:0>4X} (IR x{:0>4X}): ", self.pc, self.ir)?;
+        match &self.kind {
+            SimErrorKind::MalformedInstruction(reason) => write!(f, "Malformed instruction: {}", reason)?,
+            SimErrorKind::AccessControlViolation { address } => {
+                write!(f, "Access control violation: user mode cannot access x{:0>4X}", address)?
+            }
+            SimErrorKind::PrivilegeModeViolation => write!(f, "Privilege mode violation: RTI executed in user mode")?,
+            SimErrorKind::IllegalOpcode => write!(f, "Illegal opcode: x{:0>4X} is not an instruction", self.ir)?,
+            SimErrorKind::Interrupt { vector } => write!(f, "Interrupt x{:0>2X} was requested", vector)?,
+        }
+        if let Some(vector) = self.kind.vector() {
+            write!(
+                f,
+                ", but no handler is installed for vector x{:0>2X} (x{:0>4X} is empty)",
+                vector,
+                interrupt::INTERRUPT_VECTOR_TABLE + vector,
+            )?;
+        }
+        if let SimErrorKind::Interrupt { .. } = self.kind {
+            write!(f, "; the device's interrupts have been disabled")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SimError {}
@@ -0,0 +1,125 @@
+use super::{
+    calls::{Frame, FrameKind},
+    memory, State,
+};
+use crate::util::bits;
+
+/// Exception raised when `RTI` is executed in user mode.
+pub const PRIVILEGE_MODE_VIOLATION: u16 = 0x00;
+/// Exception raised when the reserved opcode 1101 is executed.
+pub const ILLEGAL_OPCODE: u16 = 0x01;
+/// Exception raised when user mode code accesses system space or the device registers.
+pub const ACCESS_CONTROL_VIOLATION: u16 = 0x02;
+
+/// Interrupt raised while the keyboard is ready and KBSR bit 14 is set.
+pub const KEYBOARD: u16 = 0x80;
+pub const KEYBOARD_PRIORITY: u16 = 4;
+/// Interrupt raised while the timer has expired and TMR bit 14 is set.
+pub const TIMER: u16 = 0x81;
+pub const TIMER_PRIORITY: u16 = 6;
+
+/// Entry `v` of the interrupt vector table, at this address plus `v`, holds the address of the handler for vector `v`.
+pub const INTERRUPT_VECTOR_TABLE: u16 = 0x0100;
+
+/// Addresses below this one are system space, along with the device registers.
+pub const USER_SPACE: u16 = 0x3000;
+
+impl State<'_> {
+    /// Whether the processor is running in user mode, i.e. PSR bit 15 is set.
+    pub fn user_mode(&self) -> bool {
+        self.psr < 0
+    }
+
+    /// The priority level in PSR[10:8].
+    pub fn priority(&self) -> u16 {
+        bits(self.psr, 10, 8)
+    }
+
+    /// The highest priority device interrupt that is being requested at a higher priority than the
+    /// running program, as a vector and priority level.
+    pub fn pending_interrupt(&self) -> Option<(u16, u16)> {
+        let requests = [
+            (self.mem.timer.interrupt_enable && self.mem.timer.expired, TIMER, TIMER_PRIORITY),
+            (self.mem.keyboard.interrupt_enable && self.mem.keyboard.ready(), KEYBOARD, KEYBOARD_PRIORITY),
+        ];
+        requests
+            .into_iter()
+            .filter(|&(requested, _, priority)| requested && priority > self.priority())
+            .max_by_key(|&(_, _, priority)| priority)
+            .map(|(_, vector, priority)| (vector, priority))
+    }
+
+    /// Whether a handler has been installed for `vector` in the interrupt vector table.
+    pub(super) fn has_handler(&self, vector: u16) -> bool {
+        self.mem.peek(INTERRUPT_VECTOR_TABLE + vector) != 0
+    }
+
+    /// Turns off interrupts from the device that requests `vector`, as if the program had cleared bit 14 of its
+    /// status register.
+    pub(super) fn disable_interrupt(&mut self, vector: u16) {
+        match vector {
+            KEYBOARD => self.mem.keyboard.interrupt_enable = false,
+            TIMER => self.mem.timer.interrupt_enable = false,
+            _ => {}
+        }
+    }
+
+    fn push(&mut self, val: i16) {
+        self.reg[6] = self.reg[6].wrapping_sub(1);
+        self.mem.write(self.reg[6] as u16, val);
+    }
+
+    fn pop(&mut self) -> i16 {
+        let val = self.mem.read(self.reg[6] as u16);
+        self.reg[6] = self.reg[6].wrapping_add(1);
+        val
+    }
+
+    /// Switches to supervisor mode (swapping in the supervisor stack if coming from user mode) and pushes the
+    /// old PSR and PC onto the supervisor stack, for `RTI` to return with. The priority level is raised to
+    /// `priority` if one is given.
+    pub(super) fn enter_supervisor_mode(&mut self, priority: Option<u16>) {
+        let old_psr = self.psr;
+        if self.user_mode() {
+            self.saved_usp = self.reg[6];
+            self.reg[6] = self.saved_ssp;
+        }
+        self.psr &= 0x7FFF;
+        if let Some(priority) = priority {
+            self.psr = (self.psr & !0x0700) | (priority << 8) as i16;
+        }
+        self.push(old_psr);
+        self.push(self.pc);
+    }
+
+    /// Enters supervisor mode and jumps to the handler for `vector`. Interrupts also raise the priority level
+    /// to `priority`; exceptions leave it alone.
+    ///
+    /// Returns false without changing anything if no handler has been installed for `vector`.
+    pub(super) fn initiate_interrupt(&mut self, vector: u16, priority: Option<u16>) -> bool {
+        let handler = self.mem.peek(INTERRUPT_VECTOR_TABLE + vector);
+        if handler == 0 {
+            return false;
+        }
+        self.enter_supervisor_mode(priority);
+        self.enter(Frame { site: self.pc as u16, target: handler as u16, kind: FrameKind::Interrupt(vector) });
+        self.pc = handler;
+        true
+    }
+
+    /// Executes `RTI`: pops the PC and PSR off the supervisor stack, swapping the user stack back in if
+    /// the restored PSR is in user mode.
+    pub(super) fn return_from_interrupt(&mut self) {
+        self.pc = self.pop();
+        self.psr = self.pop();
+        if self.user_mode() {
+            self.saved_ssp = self.reg[6];
+            self.reg[6] = self.saved_usp;
+        }
+    }
+
+    /// Whether the current mode is allowed to access `addr`.
+    pub(super) fn can_access(&self, addr: u16) -> bool {
+        !self.user_mode() || (USER_SPACE..memory::DEVICE_REGISTERS).contains(&addr)
+    }
+}
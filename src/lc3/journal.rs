@@ -0,0 +1,277 @@
+use std::collections::VecDeque;
+
+use super::{calls::Frame, memory::undoable, State};
+
+/// How many executed instructions are remembered. Older ones are forgotten and can no longer be stepped back over.
+pub const JOURNAL_LIMIT: usize = 100_000;
+
+/// The machine state from before one executed instruction (or interrupt), along with the memory it changed.
+/// Keeping the whole register file is simpler than tracking which register an instruction wrote, and still cheap.
+pub struct Step {
+    pub pc: i16,
+    pub ir: i16,
+    pub reg: [i16; 8],
+    pub psr: i16,
+    pub saved_usp: i16,
+    pub saved_ssp: i16,
+    /// Addresses the instruction wrote, each with the value it held before, in the order they were written.
+    pub writes: Vec<(u16, i16)>,
+    /// Writes to device registers that cannot be undone, like printing through DDR, with the values written.
+    /// Stepping back leaves these alone, but they are part of the step's trace.
+    pub device_writes: Vec<(u16, i16)>,
+    /// Characters the instruction took from the keyboard.
+    pub taken: Vec<u8>,
+    /// The keyboard's last character read and whether it was being polled, which reading KBDR and KBSR change.
+    pub keyboard_last: u8,
+    pub keyboard_polled: bool,
+    /// The vector of the interrupt that was serviced, if this step serviced one instead of executing an instruction.
+    pub interrupt: Option<u16>,
+    /// Whether the step pushed a frame onto the call stack.
+    pub called: bool,
+    /// The outermost frame, if the call stack was full and pushing a frame dropped it.
+    pub dropped: Option<Frame>,
+    /// Frames the step popped off the call stack, from the bottom up.
+    pub returned: Vec<Frame>,
+}
+
+/// An undo log of the most recently executed instructions.
+///
+/// Output already written to the display and the state of the timer are not rewound.
+#[derive(Default)]
+pub struct Journal {
+    steps: VecDeque<Step>,
+    /// The step being executed. It is kept here across the repeated attempts of a `GETC`/`IN` that is waiting
+    /// for input, so that the whole wait becomes a single step.
+    current: Option<Step>,
+}
+
+impl Journal {
+    /// The most recently finished step.
+    pub fn last(&self) -> Option<&Step> {
+        self.steps.back()
+    }
+
+    /// Marks the step being executed as servicing the interrupt `vector`.
+    pub(super) fn record_interrupt(&mut self, vector: u16) {
+        if let Some(step) = &mut self.current {
+            step.interrupt = Some(vector);
+        }
+    }
+
+    pub(super) fn record_call(&mut self, dropped: Option<Frame>) {
+        if let Some(step) = &mut self.current {
+            step.called = true;
+            step.dropped = dropped;
+        }
+    }
+
+    pub(super) fn record_returns(&mut self, frames: Vec<Frame>) {
+        if let Some(step) = &mut self.current {
+            step.returned = frames;
+        }
+    }
+}
+
+impl State<'_> {
+    /// Remembers the state before an instruction is executed.
+    pub(super) fn begin_step(&mut self) {
+        self.mem.writes.clear();
+        self.mem.reads.clear();
+        self.mem.keyboard.taken.clear();
+        if self.journal.current.is_none() {
+            self.journal.current = Some(Step {
+                pc: self.pc,
+                ir: self.ir,
+                reg: self.reg,
+                psr: self.psr,
+                saved_usp: self.saved_usp,
+                saved_ssp: self.saved_ssp,
+                writes: vec![],
+                device_writes: vec![],
+                taken: vec![],
+                keyboard_last: self.mem.keyboard.last,
+                keyboard_polled: self.mem.keyboard.polled,
+                interrupt: None,
+                called: false,
+                dropped: None,
+                returned: vec![],
+            });
+        }
+    }
+
+    /// Adds what the instruction changed to the journal, unless it is still waiting for input.
+    pub(super) fn end_step(&mut self) {
+        let Some(step) = &mut self.journal.current else {
+            return;
+        };
+        for &(addr, old, new) in &self.mem.writes {
+            if undoable(addr) {
+                step.writes.push((addr, old));
+            } else {
+                step.device_writes.push((addr, new));
+            }
+        }
+        step.taken.extend_from_slice(&self.mem.keyboard.taken);
+        if self.waiting_for_input {
+            return;
+        }
+        if self.journal.steps.len() == JOURNAL_LIMIT {
+            self.journal.steps.pop_front();
+        }
+        self.journal.steps.extend(self.journal.current.take());
+    }
+
+    /// Empties the journal, e.g. after the machine is edited by hand, which stepping back past would undo or
+    /// contradict.
+    pub fn forget_history(&mut self) {
+        self.journal.steps.clear();
+        self.journal.current = None;
+    }
+
+    /// Undoes the last executed instruction, including one that is waiting for input. Returns false if there
+    /// is nothing left in the journal to undo.
+    pub fn step_back(&mut self) -> bool {
+        let Some(step) = self.journal.current.take().or_else(|| self.journal.steps.pop_back()) else {
+            return false;
+        };
+        self.pc = step.pc;
+        self.ir = step.ir;
+        self.reg = step.reg;
+        self.psr = step.psr;
+        self.saved_usp = step.saved_usp;
+        self.saved_ssp = step.saved_ssp;
+        self.waiting_for_input = false;
+        if step.called {
+            self.calls.pop();
+        }
+        if let Some(frame) = step.dropped {
+            self.calls.insert(0, frame);
+            self.calls_dropped -= 1;
+        }
+        self.calls.extend(step.returned);
+        for &(addr, val) in step.writes.iter().rev() {
+            self.mem.poke(addr, val);
+        }
+        for &c in step.taken.iter().rev() {
+            self.mem.keyboard.untake(c);
+        }
+        self.mem.keyboard.last = step.keyboard_last;
+        self.mem.keyboard.polled = step.keyboard_polled;
+        self.mem.writes.clear();
+        self.mem.reads.clear();
+        self.mem.keyboard.taken.clear();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        calls::CALL_STACK_LIMIT,
+        memory::{DDR, KBDR},
+        tests::machine,
+        State,
+    };
+
+    /// The parts of the machine an instruction can change.
+    fn snapshot(state: &State) -> (i16, [i16; 8], i16, i16, Vec<u16>) {
+        let calls = state.calls.iter().map(|f| f.target).collect();
+        (state.pc, state.reg, state.psr, state.mem.peek(0x3010), calls)
+    }
+
+    #[test]
+    fn step_back_restores_registers_memory_and_calls() {
+        let mut state = machine(&[(
+            0x3000,
+            // AND R1, R1, #0; ADD R1, R1, #7; ST R1, x3010; JSR x3005; HALT; RET
+            &[0x5260, 0x1267, 0x320D, 0x4801, 0xF025, 0xC1C0],
+        )]);
+        let mut before = vec![];
+        for _ in 0..5 {
+            before.push(snapshot(&state));
+            state.execute_next_instruction().unwrap();
+        }
+        assert_eq!(state.mem.peek(0x3010), 7);
+        assert!(state.calls.is_empty());
+
+        while let Some(expected) = before.pop() {
+            assert!(state.step_back());
+            assert_eq!(snapshot(&state), expected);
+            if state.pc == 0x3005 {
+                assert_eq!(state.calls.len(), 1);
+            }
+        }
+        assert!(!state.step_back());
+    }
+
+    #[test]
+    fn step_back_restores_the_keyboard() {
+        // LDI R0, KBSRP; LDI R1, KBDRP; HALT; KBSRP .FILL xFE00; KBDRP .FILL xFE02
+        let mut state = machine(&[(0x3000, &[0xA002, 0xA202, 0xF025, 0xFE00, KBDR])]);
+        state.start_in_supervisor_mode();
+
+        // Polling with no key waiting
+        state.execute_next_instruction().unwrap();
+        assert!(state.mem.keyboard.polled);
+        state.step_back();
+        assert!(!state.mem.keyboard.polled);
+
+        state.mem.keyboard.push(b'a');
+        state.execute_next_instruction().unwrap();
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.reg[1], b'a' as i16);
+        assert!(!state.mem.keyboard.ready());
+
+        state.step_back();
+        assert_eq!(state.mem.keyboard.last, 0);
+        assert_eq!(state.mem.keyboard.pending, [b'a']);
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.reg[1], b'a' as i16);
+    }
+
+    #[test]
+    fn forgotten_history_cannot_be_stepped_back() {
+        // ADD R1, R1, #1
+        let mut state = machine(&[(0x3000, &[0x1261, 0x1261])]);
+        state.execute_next_instruction().unwrap();
+        state.reg[1] = 5;
+        state.forget_history();
+        assert!(!state.step_back());
+        state.execute_next_instruction().unwrap();
+        assert!(state.step_back());
+        assert_eq!(state.reg[1], 5);
+    }
+
+    #[test]
+    fn display_output_is_seen_but_not_journaled() {
+        // STI R0, DDRP; HALT; DDRP .FILL xFE06
+        let mut state = machine(&[(0x3000, &[0xB001, 0xF025, DDR])]);
+        state.start_in_supervisor_mode();
+        state.reg[0] = b'!' as i16;
+        state.execute_next_instruction().unwrap();
+        assert!(state.mem.writes.iter().any(|&(addr, _, _)| addr == DDR));
+        assert!(state.journal.last().unwrap().writes.is_empty());
+        assert_eq!(state.journal.last().unwrap().device_writes, [(DDR, b'!' as i16)]);
+        assert!(state.step_back());
+        assert_eq!(state.mem.display.output, b"!");
+    }
+
+    #[test]
+    fn the_call_stack_is_capped_and_step_back_restores_dropped_frames() {
+        // LOOP JSR LOOP
+        let mut state = machine(&[(0x3000, &[0x4FFF])]);
+        for _ in 0..CALL_STACK_LIMIT + 2 {
+            state.execute_next_instruction().unwrap();
+        }
+        assert_eq!(state.calls.len(), CALL_STACK_LIMIT);
+        assert_eq!(state.call_depth(), CALL_STACK_LIMIT + 2);
+        assert_eq!(state.calls[0].site, 0x3000);
+        for _ in 0..2 {
+            assert!(state.step_back());
+        }
+        assert_eq!(state.calls.len(), CALL_STACK_LIMIT);
+        assert_eq!(state.call_depth(), CALL_STACK_LIMIT);
+        state.step_back();
+        assert_eq!(state.calls.len(), CALL_STACK_LIMIT - 1);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+
+/// Keyboard status register. Bit 15 is set while a typed character is waiting in KBDR, and bit 14
+/// enables keyboard interrupts.
+pub const KBSR: u16 = 0xFE00;
+/// Keyboard data register. Reading it takes the waiting character.
+pub const KBDR: u16 = 0xFE02;
+/// Display status register. Bit 15 is set when the display is ready for another character.
+pub const DSR: u16 = 0xFE04;
+/// Display data register. Writing it prints the low byte.
+pub const DDR: u16 = 0xFE06;
+/// Timer status register. Bit 15 is set each time the timer interval elapses and cleared by reading it,
+/// and bit 14 enables timer interrupts.
+pub const TMR: u16 = 0xFE08;
+/// Timer interval register: the number of instructions between ticks, or 0 to stop the timer.
+pub const TMI: u16 = 0xFE0A;
+/// Machine control register. Clearing bit 15 stops the clock, halting the machine.
+pub const MCR: u16 = 0xFFFE;
+
+/// Start of the page of memory-mapped device registers.
+pub const DEVICE_REGISTERS: u16 = 0xFE00;
+
+/// Whether a write to `addr` can be undone by writing back the value it held. Writing DDR prints, and KBDR and
+/// DSR are read-only, so their old value cannot be written back.
+pub fn undoable(addr: u16) -> bool {
+    !matches!(addr, KBDR | DSR | DDR)
+}
+
+pub struct Keyboard {
+    /// Characters that have been typed but not yet read through KBDR (or `GETC`/`IN`).
+    pub pending: VecDeque<u8>,
+    /// The character most recently read, which KBDR keeps returning until another one arrives.
+    pub(super) last: u8,
+    /// Set when the program reads KBSR while no character is waiting, i.e. it is polling for input.
+    pub polled: bool,
+    /// KBSR bit 14.
+    pub interrupt_enable: bool,
+    /// Characters taken since the start of the current instruction, so that stepping back over it can return them.
+    pub taken: Vec<u8>,
+}
+
+impl Keyboard {
+    /// Types a character on the keyboard.
+    pub fn push(&mut self, c: u8) {
+        self.pending.push_back(c);
+        self.polled = false;
+    }
+
+    /// Takes the next typed character, as reading KBDR does.
+    pub fn take(&mut self) -> Option<u8> {
+        let c = self.pending.pop_front()?;
+        self.last = c;
+        self.taken.push(c);
+        Some(c)
+    }
+
+    /// Puts a taken character back at the front of the queue.
+    pub fn untake(&mut self, c: u8) {
+        self.pending.push_front(c);
+    }
+
+    /// Whether the keyboard is ready, i.e. KBSR bit 15 is set.
+    pub fn ready(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Whether the program is polling KBSR for a key. A program that only has keyboard interrupts enabled can
+    /// carry on without one.
+    pub fn wants_input(&self) -> bool {
+        !self.ready() && self.polled
+    }
+
+    fn status(&self) -> i16 {
+        ((self.ready() as u16) << 15 | (self.interrupt_enable as u16) << 14) as i16
+    }
+}
+
+/// A periodic timer that ticks every `interval` executed instructions.
+pub struct Timer {
+    pub interval: u16,
+    /// Instructions executed since the last tick.
+    count: u16,
+    /// TMR bit 15.
+    pub expired: bool,
+    /// TMR bit 14.
+    pub interrupt_enable: bool,
+}
+
+impl Timer {
+    /// Advances the timer by one instruction.
+    pub fn tick(&mut self) {
+        if self.interval == 0 {
+            return;
+        }
+        self.count += 1;
+        if self.count >= self.interval {
+            self.count = 0;
+            self.expired = true;
+        }
+    }
+
+    fn status(&self) -> i16 {
+        ((self.expired as u16) << 15 | (self.interrupt_enable as u16) << 14) as i16
+    }
+}
+
+pub struct Display {
+    /// Characters written to the display that have not yet been shown to the user.
+    pub output: Vec<u8>,
+}
+
+/// The LC-3 address space. Ordinary addresses are backed by `cells`, while the device registers
+/// at xFE00-xFFFF are routed to the keyboard, display, timer and machine control models.
+pub struct Memory {
+    cells: [i16; 65536],
+    pub keyboard: Keyboard,
+    pub display: Display,
+    pub timer: Timer,
+    mcr: i16,
+    /// The addresses written since the start of the current instruction, each with the value it held before and
+    /// the value written. This includes device registers whose writes cannot be undone; see [`undoable`].
+    pub writes: Vec<(u16, i16, i16)>,
+    /// The addresses read since the start of the current instruction.
+    pub reads: Vec<u16>,
+}
+
+impl Memory {
+    pub fn new(cells: [i16; 65536]) -> Self {
+        Memory {
+            cells,
+            keyboard: Keyboard { pending: VecDeque::new(), last: 0, polled: false, interrupt_enable: false, taken: vec![] },
+            display: Display { output: vec![] },
+            timer: Timer { interval: 0, count: 0, expired: false, interrupt_enable: false },
+            mcr: 0x8000u16 as i16,
+            writes: vec![],
+            reads: vec![],
+        }
+    }
+
+    /// Reads a word as the processor does, with whatever side effects reading a device register has, recording
+    /// the address in `reads`.
+    pub fn read(&mut self, addr: u16) -> i16 {
+        self.reads.push(addr);
+        match addr {
+            KBSR => {
+                if self.keyboard.pending.is_empty() {
+                    self.keyboard.polled = true;
+                }
+                self.keyboard.status()
+            }
+            KBDR => {
+                self.keyboard.take();
+                self.keyboard.last as i16
+            }
+            TMR => {
+                let status = self.timer.status();
+                self.timer.expired = false;
+                status
+            }
+            _ => self.peek(addr),
+        }
+    }
+
+    /// Writes a word as the processor does, recording the old and new values in `writes`.
+    pub fn write(&mut self, addr: u16, val: i16) {
+        self.writes.push((addr, self.peek(addr), val));
+        self.poke(addr, val);
+    }
+
+    /// Writes a word without recording it, for undoing writes and for changes made by the user rather than
+    /// the program. Read-only device registers, and the read-only bits of the status registers, ignore the write.
+    pub fn poke(&mut self, addr: u16, val: i16) {
+        match addr {
+            KBSR => self.keyboard.interrupt_enable = val & 0x4000 != 0,
+            TMR => self.timer.interrupt_enable = val & 0x4000 != 0,
+            TMI => {
+                self.timer.interval = val as u16;
+                self.timer.count = 0;
+            }
+            KBDR | DSR => {}
+            DDR => self.display.output.push(val as u8),
+            MCR => self.mcr = val,
+            _ => self.cells[addr as usize] = val,
+        }
+    }
+
+    /// Reads a word without disturbing any device, for displaying memory to the user.
+    pub fn peek(&self, addr: u16) -> i16 {
+        match addr {
+            KBSR => self.keyboard.status(),
+            KBDR => self.keyboard.pending.front().copied().unwrap_or(self.keyboard.last) as i16,
+            DSR => 0x8000u16 as i16,
+            DDR => 0,
+            TMR => self.timer.status(),
+            TMI => self.timer.interval as i16,
+            MCR => self.mcr,
+            _ => self.cells[addr as usize],
+        }
+    }
+
+    /// Whether the clock is running, i.e. MCR bit 15 is set.
+    pub fn clock_enabled(&self) -> bool {
+        self.mcr < 0
+    }
+
+    /// Stops the clock by clearing MCR bit 15.
+    pub fn stop_clock(&mut self) {
+        self.write(MCR, self.mcr & 0x7FFF);
+    }
+}
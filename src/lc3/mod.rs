@@ -1,12 +1,76 @@
+pub mod calls;
+pub mod error;
+pub mod interrupt;
+pub mod journal;
+pub mod memory;
+pub mod trap;
+
 use crate::util::{bits, sext};
+use calls::{Frame, FrameKind};
+use error::{SimError, SimErrorKind};
+use journal::Journal;
+use memory::Memory;
 
 pub struct State<'a> {
     pub filename: &'a str,
     pub pc: i16,
     pub ir: i16,
-    pub mem: [i16; 65536],
+    pub mem: Memory,
     pub reg: [i16; 8],
     pub psr: i16,
+    /// The stack pointer of whichever mode is not running: R6 is swapped with these when the mode changes.
+    pub saved_usp: i16,
+    pub saved_ssp: i16,
+    /// Set while a `GETC`/`IN` is waiting for a key to be typed.
+    pub waiting_for_input: bool,
+    /// What each recently executed instruction changed, for stepping backwards.
+    pub journal: Journal,
+    /// The calls that have not returned yet, innermost last, as seen from the calls and returns executed so far.
+    pub calls: Vec<Frame>,
+    /// How many of the outermost calls have been dropped from `calls` to keep it within `CALL_STACK_LIMIT`.
+    pub calls_dropped: usize,
+}
+
+impl<'a> State<'a> {
+    pub fn new(filename: &'a str, mut mem: [i16; 65536]) -> Self {
+        trap::install_trap_table(&mut mem);
+        State {
+            filename,
+            pc: 0x3000,
+            ir: 0x0000,
+            mem: Memory::new(mem),
+            reg: [0x8888u16 as i16; 8],
+            // Programs start in user mode at priority 0, so that every enabled device can interrupt them, unless
+            // they are started in supervisor mode
+            psr: 0x8000u16 as i16,
+            saved_usp: 0xFE00u16 as i16,
+            saved_ssp: 0x3000,
+            waiting_for_input: false,
+            journal: Journal::default(),
+            calls: vec![],
+            calls_dropped: 0,
+        }
+    }
+
+    /// Switches to supervisor mode at priority 0 before the program starts, so that it can use the device
+    /// registers directly and every device can interrupt it. Operating system code starts this way and enters
+    /// user mode with `RTI`, like on a real machine. R6 starts out as the supervisor stack pointer, so that
+    /// interrupts and exceptions push onto the supervisor stack.
+    pub fn start_in_supervisor_mode(&mut self) {
+        self.psr = 0;
+        self.reg[6] = self.saved_ssp;
+    }
+
+    /// Whether the program cannot make progress until a key is typed, because it is blocked in `GETC`/`IN` or
+    /// polling the keyboard.
+    pub fn wants_input(&self) -> bool {
+        (self.waiting_for_input && !self.mem.keyboard.ready()) || self.mem.keyboard.wants_input()
+    }
+
+    /// Whether the machine has halted, either through the `HALT` trap or by the program clearing MCR bit 15.
+    pub fn halted(&self) -> bool {
+        !self.mem.clock_enabled()
+    }
 }
 
 impl State<'_> {
@@ -15,13 +79,13 @@ impl State<'_> {
         println!("PC*: x{:0>4X}", self.pc);
         println!("PC*: x{}", self.pc);
         println!("IR : x{:0>4X}", self.ir);
-        println!("");
+        println!();
 
         println!("REGISTERS");
         for i in 0..8 {
             println!("R{i} : x{:0>4X}", self.reg[i]);
         }
-        println!("");
+        println!();
 
         println!("FLAGS");
         println!("CC : {:0>3b}", bits(self.psr, 2, 0));
@@ -30,21 +94,21 @@ impl State<'_> {
     pub fn set_cc(&mut self) {
         if self.reg[bits(self.ir, 11, 9) as usize] < 0 {
             self.psr = (self.psr >> 3) << 3;
-            self.psr = self.psr | 4;
+            self.psr |= 4;
             /*
             self.psr &= 0b1_1111_111_11111_000u16 as i16;
             self.psr |= 0b0_0000_000_00000_100u16 as i16;
             */
         } else if self.reg[bits(self.ir, 11, 9) as usize] == 0 {
             self.psr = (self.psr >> 3) << 3;
-            self.psr = self.psr | 2;
+            self.psr |= 2;
             /*
             self.psr &= 0b1_1111_111_11111_000u16 as i16;
             self.psr |= 0b0_0000_000_00000_010u16 as i16;
             */
         } else {
             self.psr = (self.psr >> 3) << 3;
-            self.psr = self.psr | 1;
+            self.psr |= 1;
             /*
             self.psr &= 0b1_1111_111_11111_000u16 as i16;
             self.psr |= 0b0_0000_000_00000_001u16 as i16;
@@ -52,9 +116,70 @@ impl State<'_> {
         }
     }
 
-    pub fn execute_next_instruction(&mut self) -> Result<(), &str> {
-        self.ir = self.mem[self.pc as u16 as usize];
-        self.pc += 1;
+    /// Reads memory on behalf of the running program, which may not be allowed to access `addr`.
+    fn load(&mut self, addr: u16) -> Result<i16, SimErrorKind> {
+        if !self.can_access(addr) {
+            return Err(SimErrorKind::AccessControlViolation { address: addr });
+        }
+        Ok(self.mem.read(addr))
+    }
+
+    /// Writes memory on behalf of the running program, which may not be allowed to access `addr`.
+    fn store(&mut self, addr: u16, val: i16) -> Result<(), SimErrorKind> {
+        if !self.can_access(addr) {
+            return Err(SimErrorKind::AccessControlViolation { address: addr });
+        }
+        self.mem.write(addr, val);
+        Ok(())
+    }
+
+    pub fn execute_next_instruction(&mut self) -> Result<(), SimError> {
+        if self.halted() {
+            return Ok(());
+        }
+        // Without a handler, an interrupt would be requested again before every instruction and the program could
+        // never continue, so the device's interrupts are turned off once the request has been reported.
+        if let Some((vector, _)) = self.pending_interrupt().filter(|&(vector, _)| !self.has_handler(vector)) {
+            self.disable_interrupt(vector);
+            return Err(SimError { pc: self.pc as u16, ir: self.ir, kind: SimErrorKind::Interrupt { vector } });
+        }
+        self.begin_step();
+        let result = self.step();
+        self.end_step();
+        result
+    }
+
+    fn step(&mut self) -> Result<(), SimError> {
+        // Servicing an interrupt takes the place of executing an instruction, so that the
+        // handler's first instruction can be stepped to like any other.
+        if let Some((vector, priority)) = self.pending_interrupt() {
+            // A TRAP waiting for input has already been rewound, so it is executed again after the handler's RTI.
+            self.waiting_for_input = false;
+            self.journal.record_interrupt(vector);
+            self.initiate_interrupt(vector, Some(priority));
+            return Ok(());
+        }
+        let address = self.pc as u16;
+        let result = self.execute();
+        self.mem.timer.tick();
+        let kind = match result {
+            Ok(()) => return Ok(()),
+            Err(kind) => kind,
+        };
+        // Exceptions are handled by the program if it has installed a handler, and are errors otherwise.
+        if let Some(vector) = kind.vector() {
+            if self.initiate_interrupt(vector, None) {
+                return Ok(());
+            }
+        }
+        Err(SimError { pc: address, ir: self.ir, kind })
+    }
+
+    fn execute(&mut self) -> Result<(), SimErrorKind> {
+        self.ir = self.load(self.pc as u16)?;
+        // Fetching the instruction does not count as one of the reads it makes.
+        self.mem.reads.pop();
+        self.pc = self.pc.wrapping_add(1);
         // println!(
         //     ">>> DEBUG: Current instruction is x{:0>4X}",
         //     bits(self.ir, 15, 0)
@@ -76,7 +201,7 @@ impl State<'_> {
                 // println!(">>> DEBUG: Executing ADD");
                 if bits(self.ir, 5, 5) == 0 {
                     if bits(self.ir, 4, 3) != 0 {
-                        return Err("Error: Malformed instruction: bits [4:3] of ADD using source register must be 0");
+                        return Err(SimErrorKind::MalformedInstruction("bits [4:3] of ADD using source register must be 0"));
                     }
                     // println!(">>> DEBUG: ADD mode: source register");
                     self.reg[bits(self.ir, 11, 9) as usize] = (self.reg
@@ -95,7 +220,7 @@ impl State<'_> {
                 // println!(">>> DEBUG: Executing AND");
                 if bits(self.ir, 5, 5) == 0 {
                     if bits(self.ir, 4, 3) != 0 {
-                        return Err("Error: Malformed instruction: bits [4:3] of AND using source register must be 0");
+                        return Err(SimErrorKind::MalformedInstruction("bits [4:3] of AND using source register must be 0"));
                     }
                     self.reg[bits(self.ir, 11, 9) as usize] = (self.reg
                         [bits(self.ir, 8, 6) as usize])
@@ -114,37 +239,38 @@ impl State<'_> {
             0b1100 => {
                 // println!(">>> DEBUG: Executing JMP");
                 self.pc = self.reg[bits(self.ir, 8, 6) as usize];
+                if bits(self.ir, 8, 6) == 7 {
+                    self.leave(false);
+                }
             }
             0b0100 => {
                 self.reg[7] = self.pc;
-                if bits(self.ir, 11, 11) == 0 {
+                if bits(self.ir, 11, 11) == 1 {
                     // println!(">>> DEBUG: Executing JSR");
-                    self.pc = self.pc.wrapping_add(sext(bits(self.ir, 8, 0), 9) as i16);
+                    self.pc = self.pc.wrapping_add(sext(bits(self.ir, 10, 0), 11));
                 } else {
                     // println!(">>> DEBUG: Executing JSRR");
                     self.pc = self.reg[bits(self.ir, 8, 6) as usize];
                 }
+                self.enter(Frame { site: self.reg[7].wrapping_sub(1) as u16, target: self.pc as u16, kind: FrameKind::Subroutine });
             }
             0b0010 => {
                 // println!(">>> DEBUG: Executing LD");
                 self.reg[bits(self.ir, 11, 9) as usize] =
-                    self.mem[(self.pc.wrapping_add(sext(bits(self.ir, 8, 0), 9))) as u16 as usize];
+                    self.load(self.pc.wrapping_add(sext(bits(self.ir, 8, 0), 9)) as u16)?;
                 self.set_cc();
             }
             0b1010 => {
                 // println!(">>> DEBUG: Executing LDI");
-                self.reg[bits(self.ir, 11, 9) as usize] =
-                    self.mem[self.mem[(self.pc.wrapping_add(sext(bits(self.ir, 8, 0), 9))) as u16 as usize]
-                        as u16 as usize];
+                let pointer = self.load(self.pc.wrapping_add(sext(bits(self.ir, 8, 0), 9)) as u16)?;
+                self.reg[bits(self.ir, 11, 9) as usize] = self.load(pointer as u16)?;
                 self.set_cc();
             }
             0b0110 => {
                 // println!(">>> DEBUG: Executing LDR");
-                self.reg[bits(self.ir, 11, 9) as usize] = self.mem[(self.reg
-                    [bits(self.ir, 8, 6) as usize]
-                    + sext(bits(self.ir, 5, 0), 6))
-                    as u16
-                    as usize];
+                self.reg[bits(self.ir, 11, 9) as usize] = self.load(
+                    self.reg[bits(self.ir, 8, 6) as usize].wrapping_add(sext(bits(self.ir, 5, 0), 6)) as u16,
+                )?;
                 self.set_cc();
             }
             0b1110 => {
@@ -153,28 +279,48 @@ impl State<'_> {
             }
             0b0011 => {
                 // println!(">>> DEBUG: Executing ST");
-                self.mem[(self.pc.wrapping_add(sext(bits(self.ir, 8, 0), 9))) as u16 as usize] = self.reg[bits(self.ir, 11, 9) as usize];
+                self.store(self.pc.wrapping_add(sext(bits(self.ir, 8, 0), 9)) as u16, self.reg[bits(self.ir, 11, 9) as usize])?;
             }
             0b1011 => {
                 // println!(">>> DEBUG: Executing STI");
-                self.mem[self.mem[(self.pc.wrapping_add(sext(bits(self.ir, 8, 0), 9))) as u16 as usize] as u16 as usize] = self.reg[bits(self.ir, 11, 9) as usize];
+                let pointer = self.load(self.pc.wrapping_add(sext(bits(self.ir, 8, 0), 9)) as u16)?;
+                self.store(pointer as u16, self.reg[bits(self.ir, 11, 9) as usize])?;
             }
             0b0111 => {
                 // println!(">>> DEBUG: Executing STR");
-                self.mem[(self.reg
-                    [bits(self.ir, 8, 6) as usize]
-                    + sext(bits(self.ir, 5, 0), 6))
-                    as u16
-                    as usize] = self.reg[bits(self.ir, 11, 9) as usize];
+                self.store(
+                    self.reg[bits(self.ir, 8, 6) as usize].wrapping_add(sext(bits(self.ir, 5, 0), 6)) as u16,
+                    self.reg[bits(self.ir, 11, 9) as usize],
+                )?;
             }
             0b1111 => {
                 // println!(">>> DEBUG: Executing TRAP");
+                let trapvect8 = bits(self.ir, 7, 0);
+                // The return address is saved in R7. Routines also run in supervisor mode, with the PSR and PC saved
+                // on the supervisor stack for RTI to return with; one that returns with RET stays in supervisor mode.
+                self.reg[7] = self.pc;
+                // The trap table is read on the program's behalf, so this is allowed even in user mode.
+                let routine = self.mem.read(trapvect8) as u16;
+                if trap::native_routine(trapvect8) == Some(routine) {
+                    self.execute_native_trap(trapvect8);
+                } else {
+                    self.enter_supervisor_mode(None);
+                    let site = self.pc.wrapping_sub(1) as u16;
+                    self.enter(Frame { site, target: routine, kind: FrameKind::Trap(trapvect8) });
+                    self.pc = routine as i16;
+                }
             }
             0b1101 => {
-                // println!(">>> DEBUG: UNIMPLEMENTED INSTRUCTION");
+                // println!(">>> DEBUG: Executing reserved opcode");
+                return Err(SimErrorKind::IllegalOpcode);
             }
             0b1000 => {
-                // println!(">>> DEBUG: RTI (UNIMPLEMENTED)");
+                // println!(">>> DEBUG: Executing RTI");
+                if self.user_mode() {
+                    return Err(SimErrorKind::PrivilegeModeViolation);
+                }
+                self.return_from_interrupt();
+                self.leave(true);
             }
             _ => {
                 unreachable!();
@@ -184,3 +330,167 @@ impl State<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A machine with `words` loaded at each address, starting at x3000.
+    pub(super) fn machine(words: &[(u16, &[u16])]) -> State<'static> {
+        let mut mem = [0; 65536];
+        for &(origin, block) in words {
+            for (i, &word) in block.iter().enumerate() {
+                mem[origin as usize + i] = word as i16;
+            }
+        }
+        State::new("test", mem)
+    }
+
+    #[test]
+    fn user_mode_trap_runs_a_system_space_routine_and_returns_with_rti() {
+        let mut state = machine(&[
+            (0x0030, &[0x1000]),
+            // ADD R0, R0, #1; RTI
+            (0x1000, &[0x1021, 0x8000]),
+            // TRAP x30; HALT
+            (0x3000, &[0xF030, 0xF025]),
+        ]);
+        state.psr = 0x8002u16 as i16;
+        state.reg[0] = 0;
+        state.reg[6] = 0x4000;
+
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.pc, 0x1000);
+        assert!(!state.user_mode());
+        assert_eq!(state.reg[6], 0x2FFE);
+        assert_eq!(state.saved_usp, 0x4000);
+        assert_eq!(state.mem.peek(0x2FFF), 0x8002u16 as i16);
+        assert_eq!(state.mem.peek(0x2FFE), 0x3001);
+
+        // The routine's instructions are fetched from system space without an access violation
+        state.execute_next_instruction().unwrap();
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.pc, 0x3001);
+        assert!(state.user_mode());
+        assert_eq!(state.reg[0], 1);
+        assert_eq!(state.reg[6], 0x4000);
+        assert_eq!(state.saved_ssp, 0x3000);
+        assert!(state.calls.is_empty());
+
+        state.execute_next_instruction().unwrap();
+        assert!(state.halted());
+    }
+
+    #[test]
+    fn trap_saves_the_return_address_in_r7() {
+        let mut state = machine(&[
+            (0x0030, &[0x1000]),
+            // RET
+            (0x1000, &[0xC1C0]),
+            // OUT; TRAP x30; HALT
+            (0x3000, &[0xF021, 0xF030, 0xF025]),
+        ]);
+        state.start_in_supervisor_mode();
+        state.reg[0] = b'!' as i16;
+
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.reg[7], 0x3001);
+        assert_eq!(state.mem.display.output, b"!");
+
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.reg[7], 0x3002);
+        assert_eq!(state.pc, 0x1000);
+        assert_eq!(state.calls.len(), 1);
+
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.pc, 0x3002);
+        assert!(state.calls.is_empty());
+    }
+
+    #[test]
+    fn interrupt_preempts_a_trap_waiting_for_input_which_resumes_after_rti() {
+        let mut state = machine(&[
+            (0x0180, &[0x1000]),
+            // LDI R1, KBDRP; RTI; KBDRP .FILL xFE02
+            (0x1000, &[0xA201, 0x8000, 0xFE02]),
+            // GETC
+            (0x3000, &[0xF020]),
+        ]);
+        state.start_in_supervisor_mode();
+        assert_eq!(state.reg[6], 0x3000);
+        state.mem.keyboard.interrupt_enable = true;
+
+        state.execute_next_instruction().unwrap();
+        assert!(state.waiting_for_input);
+        assert_eq!(state.pc, 0x3000);
+
+        state.mem.keyboard.push(b'a');
+        state.execute_next_instruction().unwrap();
+        assert!(!state.waiting_for_input);
+        assert_eq!(state.pc, 0x1000);
+        assert_eq!(state.journal.last().unwrap().interrupt, Some(0x80));
+        assert_eq!(state.reg[6], 0x2FFE);
+        assert_eq!(state.mem.peek(0x2FFE), 0x3000);
+
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.reg[1], b'a' as i16);
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.pc, 0x3000);
+
+        // The GETC runs again, and waits for another key
+        state.execute_next_instruction().unwrap();
+        assert!(state.waiting_for_input);
+        assert_eq!(state.pc, 0x3000);
+    }
+
+    #[test]
+    fn user_mode_programs_can_be_interrupted() {
+        let mut state = machine(&[
+            (0x0180, &[0x1000]),
+            // RTI
+            (0x1000, &[0x8000]),
+            // NOP; NOP
+            (0x3000, &[0x0000, 0x0000]),
+        ]);
+        state.mem.keyboard.interrupt_enable = true;
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.pc, 0x3001);
+
+        state.mem.keyboard.push(b'a');
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.pc, 0x1000);
+        assert!(!state.user_mode());
+        assert_eq!(state.priority(), 4);
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.pc, 0x3001);
+        assert!(state.user_mode());
+        assert_eq!(state.priority(), 0);
+    }
+
+    #[test]
+    fn an_interrupt_without_a_handler_is_reported_once() {
+        // NOP; NOP
+        let mut state = machine(&[(0x3000, &[0x0000, 0x0000])]);
+        state.mem.keyboard.interrupt_enable = true;
+        state.mem.keyboard.push(b'a');
+
+        let e = state.execute_next_instruction().unwrap_err();
+        assert!(matches!(e.kind, SimErrorKind::Interrupt { vector: 0x80 }));
+        assert!(e.to_string().ends_with("(x0180 is empty); the device's interrupts have been disabled"));
+        assert!(!state.mem.keyboard.interrupt_enable);
+        assert!(state.journal.last().is_none());
+
+        state.execute_next_instruction().unwrap();
+        assert_eq!(state.pc, 0x3001);
+        assert!(state.mem.keyboard.ready());
+    }
+
+    #[test]
+    fn user_mode_access_to_system_space_is_a_violation() {
+        // LDI R0, x3002 (through x3002 to x0200)
+        let mut state = machine(&[(0x3000, &[0xA001, 0xF025, 0x0200])]);
+        state.psr = 0x8002u16 as i16;
+        let e = state.execute_next_instruction().unwrap_err();
+        assert!(matches!(e.kind, SimErrorKind::AccessControlViolation { address: 0x0200 }));
+    }
+}
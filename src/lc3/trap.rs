@@ -0,0 +1,116 @@
+use super::{memory::DDR, State};
+
+pub const GETC: u16 = 0x20;
+pub const OUT: u16 = 0x21;
+pub const PUTS: u16 = 0x22;
+pub const IN: u16 = 0x23;
+pub const PUTSP: u16 = 0x24;
+pub const HALT: u16 = 0x25;
+
+/// Trap table entries for the natively serviced routines point into this block of system space,
+/// one word per vector, so that a program which installs its own routine in the trap table
+/// still has it called instead.
+const NATIVE_ROUTINE_BASE: u16 = 0x0400;
+
+/// Returns the address the trap table holds for `vector` when it is serviced natively,
+/// or `None` if the vector has no native service routine.
+pub fn native_routine(vector: u16) -> Option<u16> {
+    if (GETC..=HALT).contains(&vector) {
+        Some(NATIVE_ROUTINE_BASE + vector - GETC)
+    } else {
+        None
+    }
+}
+
+/// Points every trap table entry that has a native service routine at that routine,
+/// unless the loaded image already provides its own entry.
+pub fn install_trap_table(mem: &mut [i16; 65536]) {
+    for vector in GETC..=HALT {
+        if mem[vector as usize] == 0 {
+            mem[vector as usize] = native_routine(vector).unwrap() as i16;
+        }
+    }
+}
+
+impl State<'_> {
+    /// Performs the service routine for `vector` in place of the LC-3 code the trap table
+    /// would normally point to. `PC` is left pointing at the instruction after the `TRAP`,
+    /// as if the routine had returned with `RTI`.
+    ///
+    /// If the routine needs a character and none is available, the `TRAP` is rewound so that
+    /// it is executed again once input arrives.
+    pub(super) fn execute_native_trap(&mut self, vector: u16) {
+        match vector {
+            GETC => {
+                if let Some(c) = self.read_input() {
+                    self.reg[0] = c as i16;
+                }
+            }
+            OUT => {
+                self.display(&[self.reg[0] as u8]);
+            }
+            PUTS => {
+                let mut addr = self.reg[0] as u16;
+                loop {
+                    let c = self.mem.read(addr);
+                    if c == 0 {
+                        break;
+                    }
+                    self.display(&[c as u8]);
+                    addr = addr.wrapping_add(1);
+                }
+            }
+            IN => {
+                if !self.waiting_for_input {
+                    self.display(b"\nInput a character> ");
+                }
+                if let Some(c) = self.read_input() {
+                    self.reg[0] = c as i16;
+                    self.display(&[c, b'\n']);
+                }
+            }
+            PUTSP => {
+                let mut addr = self.reg[0] as u16;
+                loop {
+                    let word = self.mem.read(addr) as u16;
+                    if word == 0 {
+                        break;
+                    }
+                    self.display(&[word as u8]);
+                    if word >> 8 != 0 {
+                        self.display(&[(word >> 8) as u8]);
+                    }
+                    addr = addr.wrapping_add(1);
+                }
+            }
+            HALT => {
+                self.display(b"\n\n--- Halting the LC-3 ---\n\n");
+                self.mem.stop_clock();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Writes `text` to the display through DDR, as the LC-3 routines do.
+    fn display(&mut self, text: &[u8]) {
+        for &c in text {
+            self.mem.write(DDR, c as i16);
+        }
+    }
+
+    /// Takes the next character typed on the keyboard. If there is none, the current
+    /// `TRAP` is rewound and the machine is marked as waiting for input.
+    fn read_input(&mut self) -> Option<u8> {
+        match self.mem.keyboard.take() {
+            Some(c) => {
+                self.waiting_for_input = false;
+                Some(c)
+            }
+            None => {
+                self.waiting_for_input = true;
+                self.pc = self.pc.wrapping_sub(1);
+                None
+            }
+        }
+    }
+}
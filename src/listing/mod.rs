@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::loader::LoadError;
+
+/// The source lines of an assembled program, and which line each address was assembled from.
+pub struct Listing {
+    /// Source lines in order; line `n` of the source is at index `n - 1`.
+    pub lines: Vec<String>,
+    line_by_address: HashMap<u16, usize>,
+}
+
+impl Listing {
+    /// The index into `lines` of the line that `address` was assembled from.
+    pub fn line(&self, address: u16) -> Option<usize> {
+        self.line_by_address.get(&address).copied()
+    }
+
+    /// Parses a listing in the format written by `lasm assemble --lst` and lc3as:
+    ///
+    /// ```text
+    ///                               (   2)         .ORIG x3000
+    /// (3000) E010  1110000000010000 (   3)         LEA R0, MSG
+    /// ```
+    ///
+    /// Fields are separated by whitespace, however wide. Lines that assembled to several words, like `.STRINGZ`,
+    /// appear once per word. Lines that are not in this format, like headers, are skipped.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines: Vec<String> = vec![];
+        let mut line_by_address = HashMap::new();
+        for row in text.lines() {
+            let (address, rest) = match split_address(row) {
+                Some((address, rest)) => (Some(address), rest),
+                None => (None, row),
+            };
+            let Some((number, source)) = rest.trim_start().strip_prefix('(').and_then(|rest| rest.split_once(')'))
+            else {
+                continue;
+            };
+            let number = match number.trim().parse::<usize>() {
+                Ok(number) if number > 0 => number,
+                _ => continue,
+            };
+            if number > lines.len() {
+                lines.resize(number, String::new());
+                lines[number - 1] = source.strip_prefix(' ').unwrap_or(source).replace('\t', "    ");
+            }
+            if let Some(address) = address {
+                line_by_address.insert(address, number - 1);
+            }
+        }
+        if lines.is_empty() {
+            return Err(String::from("no listing lines found"));
+        }
+        Ok(Listing { lines, line_by_address })
+    }
+
+    /// Reads and parses the listing at `path`.
+    pub fn load(path: &str) -> Result<Self, LoadError> {
+        let text = fs::read_to_string(path).map_err(|e| LoadError::io(path, e))?;
+        Listing::parse(&text).map_err(|e| LoadError::malformed(path, e))
+    }
+}
+
+/// Splits the `(3000) E010  1110000000010000` a line starts with, if it was assembled to a word, from the rest of
+/// the line, and returns the word's address.
+fn split_address(row: &str) -> Option<(u16, &str)> {
+    let hex = |s: &str| s.len() == 4 && u16::from_str_radix(s, 16).is_ok();
+    let (address, rest) = row.trim_start().strip_prefix('(')?.split_once(')')?;
+    let (word, rest) = rest.trim_start().split_once(char::is_whitespace)?;
+    let (binary, rest) = rest.trim_start().split_once(char::is_whitespace)?;
+    if !hex(address) || !hex(word) || binary.len() != 16 || !binary.chars().all(|c| c == '0' || c == '1') {
+        return None;
+    }
+    Some((u16::from_str_radix(address, 16).ok()?, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A listing laid out the way lc3as writes them, with a header, tabs in the source and a `.STRINGZ` that
+    /// spans several lines.
+    const LC3AS: &str = "\
+lc3as listing of hello.asm
+
+                     (   1) ; Prints a greeting
+                     (   2)         .ORIG x3000
+(3000) E002  1110000000000010 (   3)\tLEA R0, MSG
+(3001) F022  1111000000100010 (   4)  PUTS
+(3002) F025  1111000000100101 (   5)  HALT
+(3003) 0048  0000000001001000 (   6) MSG  .STRINGZ \"Hi\"
+(3004) 0069  0000000001101001 (   6)
+(3005) 0000  0000000000000000 (   6)
+                     (   7)         .END
+";
+
+    #[test]
+    fn parses_lc3as_listings() {
+        let listing = Listing::parse(LC3AS).unwrap();
+        assert_eq!(listing.lines.len(), 7);
+        assert_eq!(listing.lines[0], "; Prints a greeting");
+        assert_eq!(listing.lines[2], "    LEA R0, MSG");
+        assert_eq!(listing.lines[3], " PUTS");
+        assert_eq!(listing.lines[5], "MSG  .STRINGZ \"Hi\"");
+        assert_eq!(listing.line(0x3000), Some(2));
+        assert_eq!(listing.line(0x3002), Some(4));
+        assert_eq!(listing.line(0x3005), Some(5));
+        assert_eq!(listing.line(0x3006), None);
+    }
+
+    #[test]
+    fn parses_its_own_listings() {
+        let text = "                              (   1)         .ORIG x3000\n\
+                    (3000) 5260  0101001001100000 (   2)         AND R1, R1, #0\n";
+        let listing = Listing::parse(text).unwrap();
+        assert_eq!(listing.lines, ["        .ORIG x3000", "        AND R1, R1, #0"]);
+        assert_eq!(listing.line(0x3000), Some(1));
+    }
+
+    #[test]
+    fn rejects_text_without_listing_lines() {
+        assert!(Listing::parse("ADD R1, R1, #1\n").is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+use std::io;
+
+use crate::asm::AsmError;
+
+/// Why a file could not be loaded into memory.
+#[derive(Debug)]
+pub enum LoadError {
+    Io { path: String, source: io::Error },
+    /// The file is not in the format its filetype says it is.
+    Malformed { path: String, message: String },
+    /// Assembly source failed to assemble.
+    Parse { path: String, line: usize, message: String },
+    /// A segment of `path` would overwrite part of a file loaded earlier.
+    Overlap { path: String, origin: u16, end: u16, other: String, address: u16 },
+}
+
+impl LoadError {
+    pub fn io(path: &str, source: io::Error) -> Self {
+        LoadError::Io { path: path.to_string(), source }
+    }
+
+    pub fn malformed(path: &str, message: impl Into<String>) -> Self {
+        LoadError::Malformed { path: path.to_string(), message: message.into() }
+    }
+
+    pub fn parse(path: &str, error: AsmError) -> Self {
+        LoadError::Parse { path: path.to_string(), line: error.line, message: error.message }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { path, source } => write!(f, "{}: {}", path, source),
+            LoadError::Malformed { path, message } => write!(f, "{}: Malformed input: {}", path, message),
+            LoadError::Parse { path, line, message } => write!(f, "{}:{}: {}", path, line, message),
+            LoadError::Overlap { path, origin, end, other, address } => write!(
+                f,
+                "{}: segment x{:0>4X}-x{:0>4X} overlaps with {} at x{:0>4X}",
+                path, origin, end, other, address,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -1,44 +1,133 @@
+mod error;
+
 use std::fs;
-use std::io;
+use std::path::Path;
+
+use crate::{asm, lc3::memory::DEVICE_REGISTERS};
+pub use error::LoadError;
 
 pub enum Filetype<'a> {
     Asm(&'a str),
+    /// Whitespace-separated ASCII `0`/`1` digits, 16 per word, loaded at x3000.
     PlaintextBinary(&'a str),
+    /// Big-endian words, the first of which is the address the rest are loaded at (the standard `.obj` format).
+    /// The format has no segment lengths, so a file holds exactly one segment: object files concatenated into one
+    /// cannot be told apart from a single longer segment, and must be loaded as separate files instead.
     EncodedBinary(&'a str),
 }
 
+impl<'a> Filetype<'a> {
+    /// Guesses the filetype from the file extension: `.asm` is assembly source, `.obj` is an encoded binary,
+    /// and anything else is treated as a plaintext binary.
+    pub fn from_path(path: &'a str) -> Self {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("asm") => Filetype::Asm(path),
+            Some(e) if e.eq_ignore_ascii_case("obj") => Filetype::EncodedBinary(path),
+            _ => Filetype::PlaintextBinary(path),
+        }
+    }
+}
+
+/// A run of words to be placed in memory starting at `origin`.
+pub struct Segment {
+    pub origin: u16,
+    pub words: Vec<i16>,
+}
+
+/// The memory image built from one or more input files.
+pub struct Image {
+    pub mem: [i16; 65536],
+    /// Where execution starts: the origin of the first segment of the first file.
+    pub entry: u16,
+}
+
 impl Filetype<'_> {
-    pub fn parse_to_word_array(&self) -> io::Result<[i16; 65536]> {
+    pub fn path(&self) -> &str {
+        match self {
+            Filetype::Asm(s) | Filetype::PlaintextBinary(s) | Filetype::EncodedBinary(s) => s,
+        }
+    }
+
+    pub fn parse_segments(&self) -> Result<Vec<Segment>, LoadError> {
         match self {
             Filetype::EncodedBinary(s) => {
-                let input_bytes = fs::read(s).unwrap();
-                let mut return_bytes = [0; 65536];
+                let input_bytes = fs::read(s).map_err(|e| LoadError::io(s, e))?;
                 if input_bytes.len() % 2 != 0 {
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Malformed input: input byte array does not have an even number of bytes (was {})", input_bytes.len())));
+                    return Err(LoadError::malformed(s, format!("input byte array does not have an even number of bytes (was {})", input_bytes.len())));
                 }
-                if input_bytes.len() > (0xFE00 - 0x3000) * 2 {
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Malformed input: input byte array is longer than the maximum allowed length {} (was {})", 0xFE00 - 0x3000, input_bytes.len() / 2)));
+                let mut words = input_bytes.chunks(2).map(|w| u16::from_be_bytes([w[0], w[1]]) as i16);
+                let origin = match words.next() {
+                    Some(origin) => origin as u16,
+                    None => return Err(LoadError::malformed(s, "object file is missing its origin word")),
+                };
+                let words: Vec<i16> = words.collect();
+                // Nothing can be loaded over the device registers, so words reaching them are most likely the origin
+                // and contents of another object file appended to this one.
+                if origin as usize + words.len() > DEVICE_REGISTERS as usize {
+                    return Err(LoadError::malformed(s, format!(
+                        "{} words starting at x{:0>4X} run into the device registers at x{:0>4X} (an .obj file holds one segment; load concatenated ones as separate files)",
+                        words.len(),
+                        origin,
+                        DEVICE_REGISTERS,
+                    )));
                 }
-                for i in 0..(input_bytes.len() / 2) {
-                    return_bytes[i + 0x3000] = (input_bytes[2 * i] as u16 * 2u16.pow(8) + input_bytes[2 * i + 1] as u16) as i16;
-                }
-                Ok(return_bytes)
+                Ok(vec![Segment { origin, words }])
             }
             Filetype::PlaintextBinary(s) => {
-                let input_bytes = fs::read_to_string(s).unwrap().trim().split_whitespace().collect::<String>();
-                let mut return_bytes = [0; 65536];
+                let input_bytes = fs::read_to_string(s).map_err(|e| LoadError::io(s, e))?.split_whitespace().collect::<String>();
                 if input_bytes.len() % 16 != 0 {
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Malformed input: number of input bytes is not divisible by 16 (was {})", input_bytes.len())));
+                    return Err(LoadError::malformed(s, format!("number of input bytes is not divisible by 16 (was {})", input_bytes.len())));
                 }
                 if input_bytes.len() > (0xFE00 - 0x3000) * 16 {
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Malformed input: input byte array is longer than the maximum allowed length {} (was {})", 0xFE00 - 0x3000, input_bytes.len() / 16)));
+                    return Err(LoadError::malformed(s, format!("input byte array is longer than the maximum allowed length {} (was {})", 0xFE00 - 0x3000, input_bytes.len() / 16)));
                 }
+                let mut words = vec![];
                 for i in 0..(input_bytes.len() / 16) {
-                    return_bytes[i + 0x3000] = (u16::from_str_radix(&input_bytes[i * 16..(i + 1) * 16], 2).unwrap()) as i16;
+                    let digits = &input_bytes[i * 16..(i + 1) * 16];
+                    match u16::from_str_radix(digits, 2) {
+                        Ok(word) => words.push(word as i16),
+                        Err(_) => return Err(LoadError::malformed(s, format!("word {} (`{}`) is not made of 16 binary digits", i, digits))),
+                    }
+                }
+                Ok(vec![Segment { origin: 0x3000, words }])
+            }
+            Filetype::Asm(s) => {
+                let source = fs::read_to_string(s).map_err(|e| LoadError::io(s, e))?;
+                let program = asm::assemble(&source).map_err(|e| LoadError::parse(s, e))?;
+                Ok(program
+                    .segments
+                    .into_iter()
+                    .map(|segment| Segment { origin: segment.origin, words: segment.words.into_iter().map(|w| w as i16).collect() })
+                    .collect())
+            }
+        }
+    }
+}
+
+/// Loads every file into a single memory image, refusing to let two segments claim the same address.
+pub fn load(files: &[Filetype]) -> Result<Image, LoadError> {
+    let mut mem = [0; 65536];
+    // For every address that has been loaded, the file it came from.
+    let mut owners: Vec<Option<&str>> = vec![None; 65536];
+    let mut entry = None;
+    for file in files {
+        for segment in file.parse_segments()? {
+            entry.get_or_insert(segment.origin);
+            for (i, word) in segment.words.iter().enumerate() {
+                let address = segment.origin as usize + i;
+                if let Some(owner) = owners[address] {
+                    return Err(LoadError::Overlap {
+                        path: file.path().to_string(),
+                        origin: segment.origin,
+                        end: (segment.origin as usize + segment.words.len() - 1) as u16,
+                        other: owner.to_string(),
+                        address: address as u16,
+                    });
                 }
-                Ok(return_bytes)
+                owners[address] = Some(file.path());
+                mem[address] = *word;
             }
-            _ => Ok([0; 65536]),
         }
     }
+    Ok(Image { mem, entry: entry.unwrap_or(0x3000) })
 }
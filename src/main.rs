@@ -1,8 +1,18 @@
+mod asm;
+mod batch;
+mod debugger;
+mod disasm;
+mod listing;
 mod loader;
+mod symbols;
 mod util;
 mod lc3;
+mod trace;
 mod tui;
 
+use std::fs;
+use std::path::Path;
+
 use loader::Filetype;
 use tui::render_tui;
 
@@ -18,32 +28,272 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Debug a program interactively
+    ///
+    /// Programs start in user mode at priority 0, where the device registers are out of reach; pass --supervisor
+    /// for programs that use them directly, e.g. to poll KBSR or to enable keyboard and timer interrupts.
     Tui(TuiArgs),
+    /// Assemble an .asm file into a .obj file
+    ///
+    /// A .obj file holds a single origin, so every .ORIG block after the first is written to a file of its own,
+    /// named after the output file with the block's origin added (prog.obj, prog.x4000.obj, ...). Pass all of
+    /// them to `lasm run` or `lasm tui` to load the whole program.
+    Assemble(AssembleArgs),
+    /// Turn a program back into assembly source, with labels for every address its instructions refer to
+    Disassemble(DisassembleArgs),
+    /// Run a program without the TUI, using stdin and stdout as the console
+    ///
+    /// Programs start in user mode at priority 0, where the device registers are out of reach; pass --supervisor
+    /// for programs that use them directly, e.g. to poll KBSR or to enable keyboard and timer interrupts.
+    ///
+    /// Exits with 0 when the program halts, 1 on a simulator error, 2 when the instruction limit
+    /// is reached and 3 when the program asks for input after stdin is exhausted.
+    Run(RunArgs),
+    /// Compare the execution of two programs, or of a program against a trace recorded with `lasm run --trace`,
+    /// and report the first instruction where they differ
+    ///
+    /// Files ending in .trace are read as recorded traces; anything else is loaded and run with the same input
+    /// as the other side. Exits with 0 when the traces match and 2 when they diverge.
+    DiffTrace(DiffTraceArgs),
+}
+
+/// How to set up the simulated machine, shared by every subcommand that runs a program.
+#[derive(Args)]
+struct MachineArgs {
+    /// Files to load; execution starts at the origin of the first one. Each .obj file holds a single segment,
+    /// so a program with several origins is loaded by passing one file per segment. Concatenated .obj files are
+    /// not supported: the format has no segment lengths, so the files appended to the first would be loaded as
+    /// part of its segment, and are only rejected once they run into the device registers at xFE00
+    #[arg(required = true)]
+    files: Vec<String>,
+    /// Start the timer device ticking every N instructions (programs can also set it through TMI). Timer
+    /// interrupts are still only taken once enabled through TMR bit 14, which only supervisor mode can write
+    #[arg(long, value_name = "N")]
+    timer_interval: Option<u16>,
+    /// Start in supervisor mode instead of user mode, so that the program can use the device registers (to poll
+    /// KBSR, or to enable keyboard and timer interrupts) without first setting up the machine with RTI
+    #[arg(long)]
+    supervisor: bool,
+}
+
+impl MachineArgs {
+    fn filename(&self) -> String {
+        self.files.join(", ")
+    }
+
+    fn build_state<'a>(&self, filename: &'a str) -> Result<lc3::State<'a>, loader::LoadError> {
+        let files: Vec<Filetype> = self.files.iter().map(|f| Filetype::from_path(f)).collect();
+        let image = loader::load(&files)?;
+
+        let mut state = lc3::State::new(filename, image.mem);
+        state.pc = image.entry as i16;
+        if self.supervisor {
+            state.start_in_supervisor_mode();
+        }
+        if let Some(interval) = self.timer_interval {
+            state.mem.timer.interval = interval;
+        }
+        Ok(state)
+    }
 }
 
 #[derive(Args)]
 struct TuiArgs {
+    #[command(flatten)]
+    machine: MachineArgs,
+    /// Symbol table (.sym) whose labels to show and accept in commands; can be given more than once
+    #[arg(long, value_name = "FILE")]
+    sym: Vec<String>,
+    /// Listing (.lst) to show the source of the program from, with the line at the PC highlighted
+    #[arg(long, value_name = "FILE")]
+    lst: Option<String>,
+}
+
+#[derive(Args)]
+struct AssembleArgs {
+    file: String,
+    /// Path of the object file to write (defaults to the input file with an .obj extension)
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Also write a .sym symbol table next to the object file
+    #[arg(long)]
+    sym: bool,
+    /// Also write a .lst listing next to the object file
+    #[arg(long)]
+    lst: bool,
+}
+
+#[derive(Args)]
+struct DisassembleArgs {
+    /// Program to disassemble, in any format the other subcommands load
     file: String,
+    /// Path of the .asm file to write (defaults to printing the source)
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    #[command(flatten)]
+    machine: MachineArgs,
+    /// Stop after executing this many instructions
+    #[arg(short, long)]
+    max_instructions: Option<u64>,
+    /// Print the registers and flags once the program stops
+    #[arg(long)]
+    print_state: bool,
+    /// Write a trace of every executed instruction to this file
+    #[arg(long, value_name = "FILE")]
+    trace: Option<String>,
+}
+
+#[derive(Args)]
+struct DiffTraceArgs {
+    /// The reference program or trace
+    expected: String,
+    /// The program or trace to check against it
+    actual: String,
+    /// File to use as keyboard input for both programs (by default they get none)
+    #[arg(short, long)]
+    input: Option<String>,
+    /// Stop each program after executing this many instructions
+    #[arg(short, long, default_value_t = 1_000_000)]
+    max_instructions: u64,
+    /// How many of the instructions leading up to the divergence to show
+    #[arg(short = 'C', long, default_value_t = 5)]
+    context: usize,
+    /// Start the timer device ticking every N instructions in both programs
+    #[arg(long, value_name = "N")]
+    timer_interval: Option<u16>,
+    /// Start both programs in supervisor mode instead of user mode
+    #[arg(long)]
+    supervisor: bool,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+impl DiffTraceArgs {
+    /// Reads `path` if it is a recorded trace, and otherwise runs it as a program and traces that.
+    fn trace(&self, path: &str, input: &[u8]) -> Result<Vec<trace::Record>, Box<dyn std::error::Error>> {
+        if path.ends_with(".trace") {
+            let text = fs::read_to_string(path).map_err(|e| loader::LoadError::io(path, e))?;
+            return Ok(trace::parse(&text).map_err(|e| loader::LoadError::malformed(path, e))?);
+        }
+
+        let machine = MachineArgs {
+            files: vec![path.to_string()],
+            timer_interval: self.timer_interval,
+            supervisor: self.supervisor,
+        };
+        let mut state = machine.build_state(path)?;
+        let mut trace = vec![];
+        let outcome = batch::run(&mut state, Some(self.max_instructions), &mut &input[..], &mut std::io::sink(), Some(&mut trace))?;
+        match outcome {
+            batch::Outcome::Halted => {}
+            batch::Outcome::InstructionLimit => eprintln!("{}: Stopped after executing {} instructions", path, self.max_instructions),
+            batch::Outcome::EndOfInput => eprintln!("{}: Program is waiting for input but the input is exhausted", path),
+            batch::Outcome::Error(e) => eprintln!("{}: {}", path, e),
+        }
+        Ok(trace::parse(&String::from_utf8_lossy(&trace))?)
+    }
+}
+
+fn main() {
     let cli = Cli::parse();
+    if let Err(e) = run(&cli) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
 
+fn run(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
     match &cli.command {
         Commands::Tui(tui_args) => {
-            let f = Filetype::PlaintextBinary(tui_args.file.as_str());
-            let results = f.parse_to_word_array()?;
-
-            let mut state = lc3::State {
-                filename: tui_args.file.as_str(),
-                pc: 0x3000u16 as i16,
-                ir: 0x0000,
-                mem: results,
-                reg: [0x8888u16 as i16; 8],
-                psr: 0b1_0000_111_00000_000u16 as i16,
+            let filename = tui_args.machine.filename();
+            let mut state = tui_args.machine.build_state(&filename)?;
+            let mut symbols = symbols::SymbolTable::default();
+            for path in &tui_args.sym {
+                symbols.extend(symbols::SymbolTable::load(path)?);
+            }
+
+            let listing = tui_args.lst.as_deref().map(listing::Listing::load).transpose()?;
+
+            render_tui(&mut state, &symbols, listing.as_ref())?;
+        }
+        Commands::Run(run_args) => {
+            let filename = run_args.machine.filename();
+            let mut state = run_args.machine.build_state(&filename)?;
+
+            let mut trace = match &run_args.trace {
+                Some(path) => Some(std::io::BufWriter::new(fs::File::create(path)?)),
+                None => None,
+            };
+            let outcome = batch::run(
+                &mut state,
+                run_args.max_instructions,
+                &mut batch::Stdin::new(),
+                &mut std::io::stdout().lock(),
+                trace.as_mut().map(|t| t as &mut dyn std::io::Write),
+            )?;
+            match &outcome {
+                batch::Outcome::Halted => {}
+                batch::Outcome::InstructionLimit => eprintln!("Stopped after executing {} instructions", run_args.max_instructions.unwrap()),
+                batch::Outcome::EndOfInput => eprintln!("Program is waiting for input but stdin is closed"),
+                batch::Outcome::Error(e) => eprintln!("{}", e),
+            }
+            if run_args.print_state {
+                state.print();
+            }
+            std::process::exit(outcome.exit_code());
+        }
+        Commands::DiffTrace(diff_args) => {
+            let input = match &diff_args.input {
+                Some(path) => fs::read(path).map_err(|e| loader::LoadError::io(path, e))?,
+                None => vec![],
+            };
+            let expected = diff_args.trace(&diff_args.expected, &input)?;
+            let actual = diff_args.trace(&diff_args.actual, &input)?;
+            if !trace::diff::diff(&expected, &actual, diff_args.context, &mut std::io::stdout().lock())? {
+                std::process::exit(2);
+            }
+        }
+        Commands::Disassemble(disassemble_args) => {
+            let segments = Filetype::from_path(&disassemble_args.file).parse_segments()?;
+            let source = disasm::program::disassemble_program(&segments);
+            match &disassemble_args.output {
+                Some(output) => {
+                    fs::write(output, source)?;
+                    println!("Wrote {}", output);
+                }
+                None => print!("{}", source),
+            }
+        }
+        Commands::Assemble(assemble_args) => {
+            let source = fs::read_to_string(&assemble_args.file).map_err(|e| loader::LoadError::io(&assemble_args.file, e))?;
+            let program = asm::assemble(&source).map_err(|e| loader::LoadError::parse(&assemble_args.file, e))?;
+            let output = match &assemble_args.output {
+                Some(output) => Path::new(output).to_path_buf(),
+                None => Path::new(&assemble_args.file).with_extension("obj"),
             };
 
-            render_tui(&mut state)?;
+            // The .obj format only has room for one origin, so every .ORIG block after the first gets its own file.
+            for (i, segment) in program.segments.iter().enumerate() {
+                let path = if i == 0 {
+                    output.clone()
+                } else {
+                    output.with_extension(format!("x{:04X}.obj", segment.origin))
+                };
+                fs::write(&path, segment.to_obj_bytes())?;
+                println!("Wrote {} (origin x{:04X}, {} words)", path.display(), segment.origin, segment.words.len());
+            }
+            if assemble_args.sym {
+                let path = output.with_extension("sym");
+                fs::write(&path, program.symbol_table())?;
+                println!("Wrote {}", path.display());
+            }
+            if assemble_args.lst {
+                let path = output.with_extension("lst");
+                fs::write(&path, program.listing(&source))?;
+                println!("Wrote {}", path.display());
+            }
         }
     }
     Ok(())
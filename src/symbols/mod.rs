@@ -0,0 +1,73 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+use crate::loader::LoadError;
+
+/// Labels and the addresses they stand for.
+#[derive(Default)]
+pub struct SymbolTable {
+    addresses: HashMap<String, u16>,
+    /// The first label given for each address.
+    labels: BTreeMap<u16, String>,
+}
+
+impl SymbolTable {
+    pub fn insert(&mut self, label: &str, address: u16) {
+        self.addresses.insert(label.to_string(), address);
+        self.labels.entry(address).or_insert_with(|| label.to_string());
+    }
+
+    /// The address of `label`. Labels are matched exactly if possible, and otherwise ignoring case.
+    pub fn address(&self, label: &str) -> Option<u16> {
+        self.addresses.get(label).copied().or_else(|| {
+            self.addresses
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(label))
+                .map(|(_, &address)| address)
+        })
+    }
+
+    /// The label at `address`, if there is one.
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    /// The closest label at or before `address`, and how far past it `address` is.
+    pub fn label_before(&self, address: u16) -> Option<(&str, u16)> {
+        self.labels.range(..=address).next_back().map(|(&a, label)| (label.as_str(), address - a))
+    }
+
+    /// Parses a symbol table in the format written by `lasm assemble --sym` and the standard LC-3 assemblers:
+    /// one `//\tLABEL  ADDR` line per symbol, with the address in hex. Header lines are skipped.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut table = SymbolTable::default();
+        for line in text.lines() {
+            let line = line.trim_start().strip_prefix("//").unwrap_or(line);
+            let [label, address] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                continue;
+            };
+            let address = address.strip_prefix(['x', 'X']).unwrap_or(address);
+            if let Ok(address) = u16::from_str_radix(address, 16) {
+                table.insert(label, address);
+            }
+        }
+        if table.addresses.is_empty() {
+            return Err(String::from("no symbols found"));
+        }
+        Ok(table)
+    }
+
+    /// Reads and parses the symbol table at `path`.
+    pub fn load(path: &str) -> Result<Self, LoadError> {
+        let text = fs::read_to_string(path).map_err(|e| LoadError::io(path, e))?;
+        SymbolTable::parse(&text).map_err(|e| LoadError::malformed(path, e))
+    }
+
+    /// Adds every symbol in `other`, keeping the existing label for addresses that already have one.
+    pub fn extend(&mut self, other: SymbolTable) {
+        for (address, label) in other.labels {
+            self.labels.entry(address).or_insert(label);
+        }
+        self.addresses.extend(other.addresses);
+    }
+}
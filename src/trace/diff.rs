@@ -0,0 +1,164 @@
+use std::io::{self, Write};
+
+use super::{Change, Record};
+
+/// The values a record gives for whatever `key` changes, matching registers by number and memory by address.
+/// There is more than one when a device register like DDR is written repeatedly.
+fn find(record: &Record, key: Change) -> Vec<u16> {
+    record
+        .changes
+        .iter()
+        .filter_map(|&change| match (change, key) {
+            (Change::Register(r, val), Change::Register(key, _)) if r == key => Some(val),
+            (Change::Memory(addr, val), Change::Memory(key, _)) if addr == key => Some(val),
+            (Change::Psr(val), Change::Psr(_)) => Some(val),
+            _ => None,
+        })
+        .collect()
+}
+
+fn name(change: Change) -> String {
+    match change {
+        Change::Register(r, _) => format!("R{}", r),
+        Change::Memory(addr, _) => format!("M[x{:0>4X}]", addr),
+        Change::Psr(_) => String::from("PSR"),
+    }
+}
+
+fn value(vals: &[u16]) -> String {
+    if vals.is_empty() {
+        return String::from("unchanged");
+    }
+    vals.iter().map(|val| format!("x{:0>4X}", val)).collect::<Vec<_>>().join(" ")
+}
+
+/// Explains how two records of the same instruction differ, one difference per line.
+fn explain(expected: &Record, actual: &Record) -> Vec<String> {
+    if expected.pc != actual.pc {
+        return vec![format!("PC: expected x{:0>4X}, actual x{:0>4X}", expected.pc, actual.pc)];
+    }
+    if expected.ir != actual.ir {
+        return vec![format!(
+            "Instruction at x{:0>4X}: expected x{:0>4X} ({}), actual x{:0>4X} ({})",
+            expected.pc, expected.ir, expected.text, actual.ir, actual.text,
+        )];
+    }
+    let mut lines = vec![];
+    let mut seen: Vec<String> = vec![];
+    for &change in expected.changes.iter().chain(&actual.changes) {
+        let name = name(change);
+        if seen.contains(&name) {
+            continue;
+        }
+        let (e, a) = (find(expected, change), find(actual, change));
+        if e != a {
+            lines.push(format!("{}: expected {}, actual {}", name, value(&e), value(&a)));
+        }
+        seen.push(name);
+    }
+    if expected.cc != actual.cc {
+        lines.push(format!("CC: expected {}, actual {}", expected.cc, actual.cc));
+    }
+    lines
+}
+
+/// Compares two traces and, if they differ, writes a report of the first instruction where they do to `out`,
+/// preceded by up to `context` of the instructions they agree on. Returns whether the traces match.
+pub fn diff(expected: &[Record], actual: &[Record], context: usize, out: &mut impl Write) -> io::Result<bool> {
+    let Some(i) = (0..expected.len().max(actual.len())).find(|&i| expected.get(i) != actual.get(i)) else {
+        writeln!(out, "Traces match ({} instructions)", expected.len())?;
+        return Ok(true);
+    };
+
+    writeln!(out, "Traces diverge at instruction {}:", i + 1)?;
+    for record in &expected[i.saturating_sub(context)..i] {
+        writeln!(out, "  {}", record)?;
+    }
+    match (expected.get(i), actual.get(i)) {
+        (Some(e), Some(a)) => {
+            writeln!(out, "- {}", e)?;
+            writeln!(out, "+ {}", a)?;
+            for line in explain(e, a) {
+                writeln!(out, "{}", line)?;
+            }
+        }
+        (Some(e), None) => {
+            writeln!(out, "- {}", e)?;
+            writeln!(out, "The actual trace ends after {} instructions", actual.len())?;
+        }
+        (None, Some(a)) => {
+            writeln!(out, "+ {}", a)?;
+            writeln!(out, "The expected trace ends after {} instructions", expected.len())?;
+        }
+        (None, None) => unreachable!(),
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::parse;
+
+    const EXPECTED: &str = "\
+x3000 x5260 AND R1, R1, #0           ; R1=x0000 CC=z
+x3001 x1267 ADD R1, R1, #7           ; R1=x0007 CC=p
+x3002 x3205 ST R1, x3008             ; M[x3008]=x0007 CC=p
+x3003 xF025 TRAP x25                 ; CC=p
+";
+
+    fn compare(expected: &str, actual: &str, context: usize) -> (bool, String) {
+        let mut out = vec![];
+        let matched = diff(&parse(expected).unwrap(), &parse(actual).unwrap(), context, &mut out).unwrap();
+        (matched, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn matching_traces() {
+        assert_eq!(compare(EXPECTED, EXPECTED, 2), (true, String::from("Traces match (4 instructions)\n")));
+    }
+
+    #[test]
+    fn first_divergence_is_explained() {
+        let actual = EXPECTED.replace("R1=x0007 CC=p", "R1=x0006 R2=x0001 CC=p").replace("M[x3008]=x0007", "M[x3008]=x0006");
+        let (matched, report) = compare(EXPECTED, &actual, 1);
+        assert!(!matched);
+        assert_eq!(
+            report,
+            "\
+Traces diverge at instruction 2:
+  x3000 x5260 AND R1, R1, #0           ; R1=x0000 CC=z
+- x3001 x1267 ADD R1, R1, #7           ; R1=x0007 CC=p
++ x3001 x1267 ADD R1, R1, #7           ; R1=x0006 R2=x0001 CC=p
+R1: expected x0007, actual x0006
+R2: expected unchanged, actual x0001
+"
+        );
+    }
+
+    #[test]
+    fn every_write_to_a_device_register_is_compared() {
+        let expected = "x3001 xF022 PUTS                     ; M[xFE06]=x0048 M[xFE06]=x0069 CC=-\n";
+        let actual = "x3001 xF022 PUTS                     ; M[xFE06]=x0048 M[xFE06]=x0049 CC=-\n";
+        let (_, report) = compare(expected, actual, 0);
+        assert!(report.ends_with("M[xFE06]: expected x0048 x0069, actual x0048 x0049\n"));
+    }
+
+    #[test]
+    fn divergent_control_flow_is_reported_by_pc() {
+        let actual = EXPECTED.replace("x3003 xF025", "x3004 xF025");
+        let (_, report) = compare(EXPECTED, &actual, 0);
+        assert!(report.starts_with("Traces diverge at instruction 4:\n- "));
+        assert!(report.ends_with("PC: expected x3003, actual x3004\n"));
+    }
+
+    #[test]
+    fn a_trace_that_ends_early_diverges_where_it_ends() {
+        let actual: String = EXPECTED.lines().take(3).map(|l| format!("{}\n", l)).collect();
+        let (matched, report) = compare(EXPECTED, &actual, 0);
+        assert!(!matched);
+        assert!(report.ends_with("The actual trace ends after 3 instructions\n"));
+        let (_, report) = compare(&actual, EXPECTED, 0);
+        assert!(report.ends_with("The expected trace ends after 3 instructions\n"));
+    }
+}
@@ -0,0 +1,210 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{disasm::disassemble, lc3::State, util::bits};
+
+pub mod diff;
+
+/// Something an instruction changed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Register(usize, u16),
+    Memory(u16, u16),
+    /// The PSR changed in more than its condition codes, e.g. on entering or leaving an interrupt.
+    Psr(u16),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Register(r, val) => write!(f, "R{}=x{:0>4X}", r, val),
+            Change::Memory(addr, val) => write!(f, "M[x{:0>4X}]=x{:0>4X}", addr, val),
+            Change::Psr(val) => write!(f, "PSR=x{:0>4X}", val),
+        }
+    }
+}
+
+/// One line of an execution trace, describing a single executed instruction.
+///
+/// Records are written as the address and word of the instruction, its disassembly, and after a `;` everything
+/// it changed followed by the condition codes it left, e.g.
+///
+/// ```text
+/// x3003 x1261 ADD R1, R1, #1           ; R1=x0001 CC=p
+/// x3006 x3E04 ST R7, x300B             ; M[x300B]=x3007 CC=p
+/// ```
+///
+/// An interrupt is traced on a line of its own, at the address of the instruction it arrived before. Output shows up
+/// as one `M[xFE06]` change per character written to DDR.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Record {
+    pub pc: u16,
+    pub ir: u16,
+    pub text: String,
+    pub changes: Vec<Change>,
+    pub cc: char,
+}
+
+impl Record {
+    /// Describes the instruction `state` executed last, from what it recorded in the journal. Returns `None` if
+    /// nothing has been executed.
+    pub fn last(state: &State) -> Option<Record> {
+        let step = state.journal.last()?;
+        let pc = step.pc as u16;
+        let (ir, text) = match step.interrupt {
+            Some(vector) => (step.ir as u16, format!("INTERRUPT x{:0>2X}", vector)),
+            None => (state.ir as u16, disassemble(state.ir, pc)),
+        };
+
+        let mut changes = vec![];
+        for r in 0..8 {
+            if state.reg[r] != step.reg[r] {
+                changes.push(Change::Register(r, state.reg[r] as u16));
+            }
+        }
+        let mut written: Vec<u16> = step.writes.iter().map(|&(addr, _)| addr).collect();
+        written.sort_unstable();
+        written.dedup();
+        for addr in written {
+            changes.push(Change::Memory(addr, state.mem.peek(addr) as u16));
+        }
+        // Output is traced as every word written to DDR, in order, since the register does not hold on to it
+        changes.extend(step.device_writes.iter().map(|&(addr, val)| Change::Memory(addr, val as u16)));
+        if (state.psr ^ step.psr) & !0x7 != 0 {
+            changes.push(Change::Psr(state.psr as u16));
+        }
+
+        let cc = if bits(state.psr, 2, 2) == 1 {
+            'n'
+        } else if bits(state.psr, 1, 1) == 1 {
+            'z'
+        } else if bits(state.psr, 0, 0) == 1 {
+            'p'
+        } else {
+            '-'
+        };
+        Some(Record { pc, ir, text, changes, cc })
+    }
+}
+
+/// Parses a word written as `xHHHH`.
+fn parse_word(s: &str) -> Result<u16, String> {
+    s.strip_prefix('x')
+        .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| format!("invalid word `{}`", s))
+}
+
+impl FromStr for Change {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, val) = s.split_once('=').ok_or_else(|| format!("invalid change `{}`", s))?;
+        let val = parse_word(val)?;
+        if name == "PSR" {
+            return Ok(Change::Psr(val));
+        }
+        if let Some(addr) = name.strip_prefix("M[").and_then(|a| a.strip_suffix(']')) {
+            return Ok(Change::Memory(parse_word(addr)?, val));
+        }
+        match name.strip_prefix('R').and_then(|r| r.parse::<usize>().ok()) {
+            Some(r) if r < 8 => Ok(Change::Register(r, val)),
+            _ => Err(format!("invalid change `{}`", s)),
+        }
+    }
+}
+
+impl FromStr for Record {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (instruction, changes) = s.split_once(';').ok_or_else(|| String::from("missing `;`"))?;
+        let mut fields = instruction.trim().splitn(3, ' ');
+        let pc = parse_word(fields.next().unwrap_or_default())?;
+        let ir = parse_word(fields.next().unwrap_or_default())?;
+        let text = fields.next().unwrap_or_default().trim().to_string();
+
+        let mut changes: Vec<&str> = changes.split_whitespace().collect();
+        let cc = match changes.pop().and_then(|cc| cc.strip_prefix("CC=")) {
+            Some(cc @ ("n" | "z" | "p" | "-")) => cc.chars().next().unwrap(),
+            _ => return Err(String::from("missing condition codes")),
+        };
+        let changes = changes.into_iter().map(str::parse).collect::<Result<_, _>>()?;
+        Ok(Record { pc, ir, text, changes, cc })
+    }
+}
+
+/// Parses a trace in the format [`Record`]s are written in, one per line. Errors give the line number.
+pub fn parse(text: &str) -> Result<Vec<Record>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| line.parse().map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect()
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "x{:0>4X} x{:0>4X} {:<24} ;", self.pc, self.ir, self.text)?;
+        for change in &self.changes {
+            write!(f, " {}", change)?;
+        }
+        write!(f, " CC={}", self.cc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE: &str = "\
+x3000 x5260 AND R1, R1, #0           ; R1=x0000 CC=z
+x3003 x3E04 ST R7, x3008             ; M[x3008]=x3007 CC=p
+x3004 x0000 INTERRUPT x80            ; R6=x2FFE M[x2FFE]=x3004 M[x2FFF]=x8001 PSR=x0401 CC=p
+x3005 x0E00 BR x3006                 ; CC=-
+";
+
+    #[test]
+    fn records_round_trip() {
+        let records = parse(TRACE).unwrap();
+        assert_eq!(records.len(), 4);
+        let written: String = records.iter().map(|r| format!("{}\n", r)).collect();
+        assert_eq!(written, TRACE);
+
+        let interrupt = &records[2];
+        assert_eq!((interrupt.pc, interrupt.ir, interrupt.cc), (0x3004, 0x0000, 'p'));
+        assert_eq!(interrupt.text, "INTERRUPT x80");
+        assert!(
+            interrupt.changes
+                == [
+                    Change::Register(6, 0x2FFE),
+                    Change::Memory(0x2FFE, 0x3004),
+                    Change::Memory(0x2FFF, 0x8001),
+                    Change::Psr(0x0401),
+                ]
+        );
+    }
+
+    #[test]
+    fn display_output_is_traced() {
+        let mut mem = [0; 65536];
+        // OUT
+        mem[0x3000] = 0xF021u16 as i16;
+        let mut state = State::new("test", mem);
+        state.reg[0] = b'!' as i16;
+        state.execute_next_instruction().unwrap();
+        let record = Record::last(&state).unwrap();
+        assert_eq!(record.to_string(), "x3000 xF021 OUT                      ; R7=x3001 M[xFE06]=x0021 CC=-");
+    }
+
+    #[test]
+    fn malformed_records_are_rejected() {
+        let error = |line: &str| line.parse::<Record>().err().unwrap();
+        assert_eq!(error("x3000 x5260 AND R1, R1, #0"), "missing `;`");
+        assert_eq!(error("x3000 x5260 AND R1, R1, #0 ; R1=x0000"), "missing condition codes");
+        assert_eq!(error("x3000 x5260 AND R1, R1, #0 ; R1=x0000 CC=q"), "missing condition codes");
+        assert_eq!(error("3000 x5260 AND R1, R1, #0 ; CC=z"), "invalid word `3000`");
+        assert_eq!(error("x3000 x5260 AND R1, R1, #0 ; R8=x0000 CC=z"), "invalid change `R8=x0000`");
+        assert_eq!(error("x3000 x5260 AND R1, R1, #0 ; R1 CC=z"), "invalid change `R1`");
+        assert_eq!(parse("\nx3000 x5260 AND ; CC=z\nbad\n").err().unwrap(), "line 3: missing `;`");
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{
+    debugger::{expr::{self, Expr}, Watch},
+    symbols::SymbolTable,
+};
+
+/// A command typed into the TUI's `:` command line.
+pub enum Command {
+    /// Scroll the memory viewer to an address.
+    Goto(u16),
+    /// Toggle the breakpoint at an address, or with a condition, set it to stop only when the condition holds.
+    Break(u16, Option<(String, Expr)>),
+    /// Carry on through the next hits of the breakpoint at an address.
+    Ignore(u16, u32),
+    /// Clear every breakpoint.
+    ClearBreakpoints,
+    /// Change a register or memory word. This clears the step back history, and changing the PC also clears the
+    /// call stack.
+    Set(Target, i16),
+    /// Add a watch.
+    Watch(Watch),
+    /// Remove the watch with this number in the watch list, counting from 1, or every watch.
+    Unwatch(Option<usize>),
+}
+
+/// Something that can be changed with `:set`.
+pub enum Target {
+    Register(usize),
+    Pc,
+    Psr,
+    Memory(u16),
+}
+
+/// Parses an address written as `x3000` or `0x3000` (hex), `#12288` (decimal), a label from `symbols`, or a bare
+/// number, which is read as hex.
+pub fn parse_address(s: &str, symbols: &SymbolTable) -> Result<u16, String> {
+    let parsed = if let Some(address) = symbols.address(s) {
+        Ok(address)
+    } else if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix(['x', 'X'])) {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(dec) = s.strip_prefix('#') {
+        dec.parse::<u16>()
+    } else {
+        u16::from_str_radix(s, 16)
+    };
+    parsed.map_err(|_| format!("invalid address `{}`", s))
+}
+
+/// Parses a value written like an address, or as a negative decimal such as `#-3`.
+pub fn parse_value(s: &str, symbols: &SymbolTable) -> Result<i16, String> {
+    if let Some(dec) = s.strip_prefix("#-") {
+        return match dec.parse::<u16>() {
+            Ok(v) if v <= 0x8000 => Ok((v as i16).wrapping_neg()),
+            _ => Err(format!("invalid value `{}`", s)),
+        };
+    }
+    parse_address(s, symbols).map(|v| v as i16).map_err(|_| format!("invalid value `{}`", s))
+}
+
+/// Parses `R0`-`R7`, `PC`, `PSR` or an address.
+fn parse_target(s: &str, symbols: &SymbolTable) -> Result<Target, String> {
+    match s.to_ascii_uppercase().as_str() {
+        "PC" => return Ok(Target::Pc),
+        "PSR" => return Ok(Target::Psr),
+        _ => {}
+    }
+    if let Some(Ok(r)) = s.strip_prefix(['R', 'r']).map(str::parse::<usize>) {
+        if r >= 8 {
+            return Err(format!("invalid register `{}`", s));
+        }
+        return Ok(Target::Register(r));
+    }
+    parse_address(s, symbols).map(Target::Memory)
+}
+
+pub fn parse(input: &str, symbols: &SymbolTable) -> Result<Command, String> {
+    let mut words = input.split_whitespace();
+    let name = words.next().ok_or_else(|| String::from("empty command"))?;
+    let args: Vec<&str> = words.collect();
+    let address = || match args.as_slice() {
+        [a] => parse_address(a, symbols),
+        _ => Err(format!("usage: {} <address|label>", name)),
+    };
+    match name {
+        "goto" | "g" => Ok(Command::Goto(address()?)),
+        "break" | "b" => match args.as_slice() {
+            [a] => Ok(Command::Break(parse_address(a, symbols)?, None)),
+            [a, "if", ..] => {
+                let condition = args[2..].join(" ");
+                let parsed = expr::parse(&condition, symbols)?;
+                Ok(Command::Break(parse_address(a, symbols)?, Some((condition, parsed))))
+            }
+            _ => Err(format!("usage: {} <address|label> [if <condition>]", name)),
+        },
+        "ignore" => match args.as_slice() {
+            [a, n] => {
+                let n = n.parse::<u32>().map_err(|_| format!("invalid count `{}`", n))?;
+                Ok(Command::Ignore(parse_address(a, symbols)?, n))
+            }
+            _ => Err(format!("usage: {} <address|label> <count>", name)),
+        },
+        "clear" => Ok(Command::ClearBreakpoints),
+        "set" | "s" => match args.as_slice() {
+            [target, value] => Ok(Command::Set(parse_target(target, symbols)?, parse_value(value, symbols)?)),
+            _ => Err(format!("usage: {} <register|PC|PSR|address> <value>", name)),
+        },
+        "watch" | "w" => {
+            let usage = || format!("usage: {} <register> [value] | {} <address|label> [r|w|rw]", name, name);
+            let (target, rest) = args.split_first().ok_or_else(usage)?;
+            match (parse_target(target, symbols)?, rest) {
+                (Target::Register(register), []) => Ok(Command::Watch(Watch::Register { register, value: None })),
+                (Target::Register(register), [value]) => {
+                    Ok(Command::Watch(Watch::Register { register, value: Some(parse_value(value, symbols)?) }))
+                }
+                (Target::Memory(address), access) => {
+                    let (read, write) = match access {
+                        [] | ["w"] => (false, true),
+                        ["r"] => (true, false),
+                        ["rw"] => (true, true),
+                        _ => return Err(usage()),
+                    };
+                    Ok(Command::Watch(Watch::Memory { address, read, write }))
+                }
+                _ => Err(usage()),
+            }
+        }
+        "unwatch" => match args.as_slice() {
+            [] => Ok(Command::Unwatch(None)),
+            [n] => match n.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(Command::Unwatch(Some(n))),
+                _ => Err(format!("invalid watch number `{}`", n)),
+            },
+            _ => Err(format!("usage: {} [number]", name)),
+        },
+        "pc" => match args.as_slice() {
+            [value] => Ok(Command::Set(Target::Pc, parse_value(value, symbols)?)),
+            _ => Err(format!("usage: {} <address|label>", name)),
+        },
+        _ => Err(format!("unknown command `{}`", name)),
+    }
+}
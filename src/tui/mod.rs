@@ -1,31 +1,163 @@
-use std::collections::{VecDeque, vec_deque};
-
 use ratatui::{
     prelude::*,
     widgets::{Paragraph, Block, Borders},
 };
 
+mod command;
+
 use crate::{
-    lc3::State,
+    debugger::{Debugger, Stop},
+    disasm::disassemble_with_symbols,
+    lc3::{calls::FrameKind, interrupt::KEYBOARD, State},
+    listing::Listing,
+    symbols::SymbolTable,
     util::bits,
 };
+use command::{Command, Target};
+
+/// How many instructions to execute between redraws while the program is running.
+const INSTRUCTIONS_PER_FRAME: usize = 10_000;
+
+fn breakpoint_status(address: u16, set: bool) -> String {
+    if set {
+        format!("Breakpoint set at x{:0>4X}", address)
+    } else {
+        format!("Breakpoint cleared at x{:0>4X}", address)
+    }
+}
 
-pub fn render_tui(lc3_state: &mut State) -> Result<(), Box<dyn std::error::Error>> {
+/// The label at `address`, or the address itself if it has none.
+fn address_name(address: u16, symbols: &SymbolTable) -> String {
+    match symbols.label(address) {
+        Some(label) => label.to_string(),
+        None => format!("x{:0>4X}", address),
+    }
+}
+
+/// Feedback from the last command or run, shown in the bottom line.
+enum Status {
+    Info(String),
+    Error(String),
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Status::Info(String::new())
+    }
+}
+
+pub fn render_tui(
+    lc3_state: &mut State,
+    symbols: &SymbolTable,
+    listing: Option<&Listing>,
+) -> Result<(), Box<dyn std::error::Error>> {
     // startup: Enable raw mode for the terminal, giving us fine control over user input
     crossterm::terminal::enable_raw_mode()?;
     crossterm::execute!(std::io::stderr(), crossterm::terminal::EnterAlternateScreen)?;
 
-    // Initialize the terminal backend using crossterm
-    let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stderr()))?;
+    // Initialize the terminal backend using crossterm, and always restore the terminal afterwards,
+    // even if drawing or reading input failed
+    let result = Terminal::new(CrosstermBackend::new(std::io::stderr()))
+        .map_err(|e| e.into())
+        .and_then(|mut terminal| run_tui(&mut terminal, lc3_state, symbols, listing));
 
+    // shutdown down: reset terminal back to original state
+    crossterm::execute!(std::io::stderr(), crossterm::terminal::LeaveAlternateScreen)?;
+    crossterm::terminal::disable_raw_mode()?;
+
+    result
+}
+
+fn run_tui(
+    terminal: &mut Terminal<CrosstermBackend<std::io::Stderr>>,
+    lc3_state: &mut State,
+    symbols: &SymbolTable,
+    listing: Option<&Listing>,
+) -> Result<(), Box<dyn std::error::Error>> {
     let mut memory_render_offset = 0usize;
     let mut memory_render_window_width = 0usize;
+    // The selected row of the memory viewer, which is kept in view. While following the PC, it moves to the PC
+    // whenever the PC changes.
+    let mut memory_cursor = lc3_state.pc as u16 as usize;
+    let mut follow_pc = true;
+    let mut last_pc = lc3_state.pc;
+
+    // The text typed after `:`, while the command line is open
+    let mut command_line: Option<String> = None;
+    // Feedback from the last command or run, shown in place of the command line
+    let mut status = Status::default();
+
+    // The register highlighted in the registers pane, which `e` edits instead of memory
+    let mut selected_register: Option<usize> = None;
 
-    let mut memory_traverse_mode = false;
-    let mut memory_traverse_address = 0usize;
+    let mut debugger = Debugger::default();
+    // Whether the program is being continued rather than stepped one instruction at a time
+    let mut running = false;
+
+    // Everything the program has written to the display, and how many lines up from the bottom the console is scrolled
+    let mut console_output = String::new();
+    let mut console_scroll = 0usize;
+    let mut console_window_height = 0usize;
+    // The source line last shown at the center of the source pane, kept while the PC is outside the listing
+    let mut source_line = 0usize;
+    // While the console has focus, key presses are sent to the simulated keyboard instead of being keybinds.
+    // It takes focus by itself when the program waits for input or polls the keyboard, and gives it back once a key is typed.
+    let mut console_focus = false;
+    let mut console_focus_auto = false;
+    let mut wanted_input = false;
 
     // Main application loop
     loop {
+        if running {
+            match debugger.run(lc3_state, INSTRUCTIONS_PER_FRAME) {
+                Some(Stop::Breakpoint) => {
+                    running = false;
+                    let hits = debugger.breakpoints.get(&(lc3_state.pc as u16)).map_or(0, |b| b.hits);
+                    status = Status::Info(format!("Stopped at breakpoint x{:0>4X} (hit {})", lc3_state.pc, hits));
+                }
+                Some(Stop::Watch(reason)) => {
+                    running = false;
+                    status = Status::Info(format!("Watch triggered: {}", reason));
+                }
+                Some(Stop::Returned(frame)) => {
+                    running = false;
+                    status = Status::Info(format!(
+                        "Returned from {} to x{:0>4X}",
+                        address_name(frame.target, symbols),
+                        lc3_state.pc,
+                    ));
+                }
+                Some(Stop::Halted) => {
+                    running = false;
+                    status = Status::Info(String::from("Program halted"));
+                }
+                Some(Stop::Error(e)) => {
+                    running = false;
+                    status = Status::Error(e.to_string());
+                }
+                None => {}
+            }
+        }
+
+        let wants_input = lc3_state.wants_input();
+        if wants_input && !wanted_input {
+            console_focus = true;
+            console_focus_auto = true;
+        }
+        wanted_input = wants_input;
+
+        if follow_pc && lc3_state.pc != last_pc {
+            memory_cursor = lc3_state.pc as u16 as usize;
+        }
+        last_pc = lc3_state.pc;
+
+        for c in lc3_state.mem.display.output.drain(..) {
+            match c {
+                b'\n' | b'\t' | 0x20..=0x7E => console_output.push(c as char),
+                _ => {}
+            }
+        }
+
         // Render the UI
         terminal.draw(|f| {
             let outer_layout = Layout::default()
@@ -41,22 +173,30 @@ pub fn render_tui(lc3_state: &mut State) -> Result<(), Box<dyn std::error::Error
                 .direction(Direction::Horizontal)
                 .constraints(vec![
                     Constraint::Percentage(30),
-                    Constraint::Percentage(10),
-                    Constraint::Percentage(10),
-                    Constraint::Percentage(50),
+                    Constraint::Percentage(8),
+                    Constraint::Percentage(8),
+                    Constraint::Percentage(18),
+                    Constraint::Percentage(36),
                 ])
                 .split(outer_layout[1]);
             
             let instructions_and_registers_layout = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints(vec![
+                    Constraint::Percentage(20),
+                    Constraint::Percentage(20),
                     Constraint::Percentage(30),
-                    Constraint::Percentage(40),
                     Constraint::Percentage(30),
                 ])
                 .split(bottom_layout[0]);
 
             memory_render_window_width = (bottom_layout[1].height - 3) as usize;
+            // Scroll by a line when the cursor moves just past the bottom, and otherwise bring it to the top
+            if memory_cursor == memory_render_offset + memory_render_window_width {
+                memory_render_offset += 1;
+            } else if !(memory_render_offset..memory_render_offset + memory_render_window_width).contains(&memory_cursor) {
+                memory_render_offset = memory_cursor.min(65536 - memory_render_window_width);
+            }
 
             f.render_widget(
                 Paragraph::new(format!("Current file: {}", lc3_state.filename))
@@ -69,7 +209,7 @@ pub fn render_tui(lc3_state: &mut State) -> Result<(), Box<dyn std::error::Error
 
             let instruction_state: Text = vec![
                 Line::from(format!("PC: x{:0>4X}", lc3_state.pc)),
-                Line::from(format!("IR: x{:0<4X}", lc3_state.ir)),
+                Line::from(format!("IR: x{:0>4X}", lc3_state.ir)),
                 Line::from(
                     format!(
                         "CC: {}", 
@@ -80,8 +220,16 @@ pub fn render_tui(lc3_state: &mut State) -> Result<(), Box<dyn std::error::Error
                         else { "-" },
                     )
                 ),
-                // TODO!
-                // Line::from(format!("Decoded current instruction: x{:0<4X}", lc3_state.ir)),
+                Line::from(format!(
+                    "PSR: x{:0>4X} ({} mode, priority {})",
+                    lc3_state.psr,
+                    if lc3_state.user_mode() { "user" } else { "supervisor" },
+                    lc3_state.priority(),
+                )),
+                Line::from(format!(
+                    "Next: {}",
+                    disassemble_with_symbols(lc3_state.mem.peek(lc3_state.pc as u16), lc3_state.pc as u16, symbols),
+                )),
             ].into();
 
             f.render_widget(
@@ -94,12 +242,57 @@ pub fn render_tui(lc3_state: &mut State) -> Result<(), Box<dyn std::error::Error
                 instructions_and_registers_layout[0],
             );
 
+            // Innermost call first, like a debugger's backtrace
+            let call_stack: Vec<Line> = lc3_state.calls
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(i, frame)| {
+                    let kind = match frame.kind {
+                        FrameKind::Subroutine => String::new(),
+                        FrameKind::Trap(vector) => format!(" (TRAP x{:0>2X})", vector),
+                        FrameKind::Interrupt(vector) if vector < KEYBOARD => format!(" (exception x{:0>2X})", vector),
+                        FrameKind::Interrupt(vector) => format!(" (interrupt x{:0>2X})", vector),
+                    };
+                    // A label further back than a PC-relative offset can reach most likely belongs to something else
+                    let site = match symbols.label_before(frame.site).filter(|&(_, offset)| offset < 0x100) {
+                        Some((label, 0)) => label.to_string(),
+                        Some((label, offset)) => format!("{}+{} (x{:0>4X})", label, offset, frame.site),
+                        None => format!("x{:0>4X}", frame.site),
+                    };
+                    Line::from(format!("#{} {}{} from {}", i, address_name(frame.target, symbols), kind, site))
+                })
+                .collect();
+
+            f.render_widget(
+                Paragraph::new(call_stack)
+                    .block(
+                        Block::default()
+                            .title(" call stack ")
+                            .borders(Borders::all())
+                    ),
+                instructions_and_registers_layout[1],
+            );
+
             let mut register_state: Vec<Line> = vec![];
 
             for i in 0..8 {
-                register_state.push(Line::from(format!("R{}: x{:0>4X}", i, lc3_state.reg[i])));
+                let line = format!("R{}: x{:0>4X}", i, lc3_state.reg[i]);
+                if selected_register == Some(i) {
+                    register_state.push(Line::styled(line, Style::default().add_modifier(Modifier::REVERSED)));
+                } else {
+                    register_state.push(Line::from(line));
+                }
             }
 
+            let registers_and_watches_layout = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![
+                    Constraint::Percentage(40),
+                    Constraint::Percentage(60),
+                ])
+                .split(instructions_and_registers_layout[2]);
+
             f.render_widget(
                 Paragraph::new(register_state)
                     .block(
@@ -109,12 +302,44 @@ pub fn render_tui(lc3_state: &mut State) -> Result<(), Box<dyn std::error::Error
                                 Borders::ALL
                             )
                     ),
-                instructions_and_registers_layout[1],
+                registers_and_watches_layout[0],
+             );
+
+            let breakpoint_list = debugger.breakpoints
+                .iter()
+                .map(|(&address, breakpoint)| {
+                    let address = address_name(address, symbols);
+                    Line::from(Span::styled(format!("* {} {}", address, breakpoint), Style::default().fg(Color::Red)))
+                });
+            let watch_list: Vec<Line> = breakpoint_list
+                .chain(debugger.watches
+                    .iter()
+                    .enumerate()
+                    .map(|(i, watch)| Line::from(format!("{}: {}", i + 1, watch))))
+                .collect();
+
+            f.render_widget(
+                Paragraph::new(watch_list)
+                    .block(
+                        Block::default()
+                            .title(" breakpoints & watches ")
+                            .borders(
+                                Borders::ALL
+                            )
+                    ),
+                registers_and_watches_layout[1],
              );
 
             let keybinds: Vec<Line> = vec![
-                Line::from("j/k: scroll memory viewer up/down"),
-                Line::from("n: execute next instruction"),
+                Line::from("j/k: move memory cursor, f: follow PC"),
+                Line::from("n/N: step forward/back, s/o: step over/out"),
+                Line::from("c/C: continue or pause/reverse-continue"),
+                Line::from("b: toggle breakpoint at memory cursor"),
+                Line::from("r: select register, e: edit register/memory"),
+                Line::from(":goto/:pc <addr|label>, :set, :clear"),
+                Line::from(":break <addr> [if <cond>], :ignore <addr> <n>"),
+                Line::from(":watch <reg|addr>, :unwatch [n]"),
+                Line::from("tab: console focus, PgUp/PgDn: scroll it"),
                 Line::from("q: quit"),
             ];
 
@@ -127,47 +352,150 @@ pub fn render_tui(lc3_state: &mut State) -> Result<(), Box<dyn std::error::Error
                                 Borders::ALL
                             )
                     ),
-                instructions_and_registers_layout[2],
+                instructions_and_registers_layout[3],
              );
 
             let mut memory_addresses: Vec<Line> = vec![];
             let mut memory_values: Vec<Line> = vec![];
+            let mut memory_instructions: Vec<Line> = vec![];
 
             memory_addresses.push(Line::from("Address"));
             memory_values.push(Line::from("Value"));
+            memory_instructions.push(Line::from("Instruction"));
 
-            for i in memory_render_offset..(memory_render_window_width + memory_render_offset) {
-                memory_addresses.push(Line::from(format!("x{:0>4X}", i)));
-                memory_values.push(Line::from(format!("x{:0>4X}", lc3_state.mem[i])));
+            for i in memory_render_offset..(memory_render_window_width + memory_render_offset).min(65536) {
+                let address = i as u16;
+                let mut row_style = Style::default();
+                if address == lc3_state.pc as u16 {
+                    row_style = row_style.fg(Color::Green).add_modifier(Modifier::BOLD);
+                }
+                if i == memory_cursor {
+                    row_style = row_style.add_modifier(Modifier::REVERSED);
+                }
+                if debugger.breakpoints.contains_key(&address) {
+                    memory_addresses.push(Line::styled(format!("x{:0>4X} *", i), row_style.fg(Color::Red)));
+                } else {
+                    memory_addresses.push(Line::styled(format!("x{:0>4X}", i), row_style));
+                }
+                // Words written by the last instruction
+                let value_style = if lc3_state.mem.writes.iter().any(|&(a, _, _)| a == address) {
+                    row_style.fg(Color::Magenta)
+                } else {
+                    row_style
+                };
+                memory_values.push(Line::styled(format!("x{:0>4X}", lc3_state.mem.peek(address)), value_style));
+                let instruction = Span::styled(disassemble_with_symbols(lc3_state.mem.peek(address), address, symbols), row_style);
+                match symbols.label(address) {
+                    Some(label) => memory_instructions.push(Line::from(vec![
+                        Span::styled(format!("{}: ", label), row_style.fg(Color::Cyan)),
+                        instruction,
+                    ])),
+                    None => memory_instructions.push(Line::from(instruction)),
+                }
             }
 
             f.render_widget(
                 Paragraph::new(memory_addresses)
                     .block(Block::default()
-                       .title(" memory viewer ")
+                       .title(if follow_pc { " memory viewer (following PC) " } else { " memory viewer " })
                        .borders(Borders::LEFT.union(Borders::TOP).union(Borders::BOTTOM))),
                 bottom_layout[1]);
 
             f.render_widget(
                 Paragraph::new(memory_values)
-                    .block(Block::default().borders(Borders::RIGHT.union(Borders::TOP).union(Borders::BOTTOM))),
+                    .block(Block::default().borders(Borders::TOP.union(Borders::BOTTOM))),
                 bottom_layout[2]);
 
             f.render_widget(
-                Paragraph::new("coming soon!")
+                Paragraph::new(memory_instructions)
+                    .block(Block::default().borders(Borders::RIGHT.union(Borders::TOP).union(Borders::BOTTOM))),
+                bottom_layout[3]);
+
+            // With a listing loaded, the source pane takes the top of the console's column
+            let console_area = match listing {
+                Some(listing) => {
+                    let source_and_console_layout = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints(vec![
+                            Constraint::Percentage(60),
+                            Constraint::Percentage(40),
+                        ])
+                        .split(bottom_layout[4]);
+
+                    let pc_line = listing.line(lc3_state.pc as u16);
+                    if let Some(line) = pc_line {
+                        source_line = line;
+                    }
+                    let source_window_height = source_and_console_layout[0].height.saturating_sub(2) as usize;
+                    let source_start = source_line
+                        .saturating_sub(source_window_height / 2)
+                        .min(listing.lines.len().saturating_sub(source_window_height));
+                    let source_text: Vec<Line> = listing.lines
+                        .iter()
+                        .enumerate()
+                        .skip(source_start)
+                        .take(source_window_height)
+                        .map(|(i, text)| {
+                            let text = format!("{:>4}  {}", i + 1, text);
+                            if pc_line == Some(i) {
+                                Line::styled(text, Style::default().fg(Color::Green).add_modifier(Modifier::BOLD | Modifier::REVERSED))
+                            } else {
+                                Line::from(text)
+                            }
+                        })
+                        .collect();
+
+                    f.render_widget(
+                        Paragraph::new(source_text)
+                            .block(Block::default()
+                               .title(" source ")
+                               .borders(Borders::ALL)),
+                        source_and_console_layout[0]);
+
+                    source_and_console_layout[1]
+                }
+                None => bottom_layout[4],
+            };
+
+            console_window_height = console_area.height.saturating_sub(2) as usize;
+            let console_lines: Vec<&str> = console_output.split('\n').collect();
+            console_scroll = console_scroll.min(console_lines.len().saturating_sub(console_window_height));
+            let console_end = console_lines.len() - console_scroll;
+            let console_start = console_end.saturating_sub(console_window_height);
+            let console_text: Vec<Line> = console_lines[console_start..console_end]
+                .iter()
+                .map(|l| Line::from(*l))
+                .collect();
+
+            f.render_widget(
+                Paragraph::new(console_text)
                     .block(Block::default()
-                       .title(" console ")
+                       .title(
+                           if console_focus {
+                               " console (keyboard input, tab to leave) "
+                           } else if lc3_state.wants_input() {
+                               " console (waiting for input, tab to type) "
+                           } else {
+                               " console "
+                           }
+                       )
+                       .border_style(
+                           if console_focus {
+                               Style::default().fg(Color::Yellow)
+                           } else {
+                               Style::default()
+                           }
+                       )
                        .borders(Borders::ALL)),
-                bottom_layout[3]);
+                console_area);
 
             f.render_widget(
                 Paragraph::new(
-                    if !memory_traverse_mode {
-                        String::from("")
-                    } else if memory_traverse_address == 0 {
-                        String::from(":goto x")
-                    } else {
-                        format!(":goto x{:X}", memory_traverse_address)
+                    match (&command_line, &status) {
+                        (Some(command), _) => Line::from(format!(":{}", command)),
+                        (None, _) if running => Line::from("Running... (c to pause)"),
+                        (None, Status::Info(message)) => Line::from(message.as_str()),
+                        (None, Status::Error(message)) => Line::styled(message.as_str(), Style::default().fg(Color::Red)),
                     }
                 )
                     .block(Block::default()
@@ -176,51 +504,234 @@ pub fn render_tui(lc3_state: &mut State) -> Result<(), Box<dyn std::error::Error
 
         })?;
 
-        // Check for user input every 250 milliseconds
-        if crossterm::event::poll(std::time::Duration::from_millis(250))? {
+        // Check for user input every 250 milliseconds, or between frames while running, unless the program
+        // cannot run until a key is typed
+        let timeout = if running && !lc3_state.wants_input() {
+            std::time::Duration::ZERO
+        } else {
+            std::time::Duration::from_millis(250)
+        };
+        if crossterm::event::poll(timeout)? {
             // If a key event occurs, handle it
             if let crossterm::event::Event::Key(key) = crossterm::event::read()? {
                 if key.kind == crossterm::event::KeyEventKind::Press {
-                    if memory_traverse_mode {
-                        match key.code {
-                            crossterm::event::KeyCode::Char(e) => {
-                                if let Some(digit) = e.to_digit(16) {
-                                    memory_traverse_address = memory_traverse_address.saturating_mul(16).saturating_add(digit as usize);
+                    match key.code {
+                        crossterm::event::KeyCode::Tab => {
+                            console_focus = !console_focus;
+                            console_focus_auto = false;
+                            continue;
+                        }
+                        crossterm::event::KeyCode::PageUp => {
+                            console_scroll = console_scroll.saturating_add(console_window_height / 2);
+                            continue;
+                        }
+                        crossterm::event::KeyCode::PageDown => {
+                            console_scroll = console_scroll.saturating_sub(console_window_height / 2);
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    if console_focus {
+                        let c = match key.code {
+                            crossterm::event::KeyCode::Char(c) if c.is_ascii() => Some(c as u8),
+                            crossterm::event::KeyCode::Enter => Some(b'\n'),
+                            crossterm::event::KeyCode::Backspace => Some(0x08),
+                            crossterm::event::KeyCode::Esc => Some(0x1B),
+                            _ => None,
+                        };
+                        if let Some(c) = c {
+                            lc3_state.mem.keyboard.push(c);
+                            // Finish the TRAP that was waiting for this key
+                            if lc3_state.waiting_for_input && !running {
+                                if let Err(e) = debugger.step(lc3_state) {
+                                    status = Status::Error(e.to_string());
                                 }
                             }
+                            if console_focus_auto {
+                                console_focus = false;
+                                console_focus_auto = false;
+                            }
+                        }
+                        continue;
+                    }
+                    if let Some(command) = &mut command_line {
+                        match key.code {
+                            crossterm::event::KeyCode::Char(c) => {
+                                command.push(c);
+                            }
                             crossterm::event::KeyCode::Backspace => {
-                                memory_traverse_address /= 16;
+                                command.pop();
                             }
                             crossterm::event::KeyCode::Esc => {
-                                memory_traverse_mode = false;
-                                memory_traverse_address = 0;
+                                command_line = None;
                             }
                             crossterm::event::KeyCode::Enter => {
-                                if memory_traverse_address + memory_render_window_width < 65536 {
-                                    memory_render_offset = memory_traverse_address;
-                                } else {
-                                    memory_render_offset = 65536 - memory_render_window_width;
+                                match command::parse(command, symbols) {
+                                    Ok(Command::Goto(address)) => {
+                                        let address = address as usize;
+                                        if address + memory_render_window_width < 65536 {
+                                            memory_render_offset = address;
+                                        } else {
+                                            memory_render_offset = 65536 - memory_render_window_width;
+                                        }
+                                        memory_cursor = address;
+                                        status = Status::default();
+                                    }
+                                    Ok(Command::Break(address, None)) => {
+                                        status = Status::Info(breakpoint_status(address, debugger.toggle_breakpoint(address)));
+                                    }
+                                    Ok(Command::Break(address, Some(condition))) => {
+                                        status = Status::Info(format!("Breakpoint at x{:0>4X} stops if {}", address, condition.0));
+                                        debugger.breakpoints.entry(address).or_default().condition = Some(condition);
+                                    }
+                                    Ok(Command::Ignore(address, count)) => {
+                                        if let Some(breakpoint) = debugger.breakpoints.get_mut(&address) {
+                                            breakpoint.ignore = count;
+                                            status = Status::Info(format!("Ignoring the next {} hits of the breakpoint at x{:0>4X}", count, address));
+                                        } else {
+                                            status = Status::Error(format!("There is no breakpoint at x{:0>4X}", address));
+                                        }
+                                    }
+                                    Ok(Command::ClearBreakpoints) => {
+                                        debugger.breakpoints.clear();
+                                        status = Status::Info(String::from("All breakpoints cleared"));
+                                    }
+                                    Ok(Command::Watch(watch)) => {
+                                        status = Status::Info(format!("Watching {}", watch));
+                                        debugger.watches.push(watch);
+                                    }
+                                    Ok(Command::Unwatch(None)) => {
+                                        debugger.watches.clear();
+                                        status = Status::Info(String::from("All watches removed"));
+                                    }
+                                    Ok(Command::Unwatch(Some(n))) => {
+                                        if n <= debugger.watches.len() {
+                                            let watch = debugger.watches.remove(n - 1);
+                                            status = Status::Info(format!("Stopped watching {}", watch));
+                                        } else {
+                                            status = Status::Error(format!("There is no watch {}", n));
+                                        }
+                                    }
+                                    Ok(Command::Set(target, value)) => {
+                                        // Stepping back would restore the old values, so the edit starts a new history
+                                        lc3_state.forget_history();
+                                        let mut forgotten = "step back history";
+                                        let name = match target {
+                                            Target::Register(r) => {
+                                                lc3_state.reg[r] = value;
+                                                format!("R{}", r)
+                                            }
+                                            Target::Pc => {
+                                                lc3_state.pc = value;
+                                                // A TRAP that was waiting for input is abandoned
+                                                lc3_state.waiting_for_input = false;
+                                                // The calls in progress may never return from here
+                                                lc3_state.clear_calls();
+                                                forgotten = "step back history and call stack";
+                                                String::from("PC")
+                                            }
+                                            Target::Psr => {
+                                                lc3_state.psr = value;
+                                                String::from("PSR")
+                                            }
+                                            Target::Memory(address) => {
+                                                lc3_state.mem.poke(address, value);
+                                                format!("x{:0>4X}", address)
+                                            }
+                                        };
+                                        status = Status::Info(format!(
+                                            "Set {} to x{:0>4X} ({} cleared)",
+                                            name, value, forgotten
+                                        ));
+                                    }
+                                    Err(e) => {
+                                        status = Status::Error(e);
+                                    }
                                 }
+                                command_line = None;
                             }
                             _ => {}
                         }
+                        continue;
                     }
                     match key.code {
-                        crossterm::event::KeyCode::Char('j') => {
-                            if memory_render_offset + memory_render_window_width < 65536 {
-                                memory_render_offset += 1;
-                            }
+                        crossterm::event::KeyCode::Char('j') if memory_cursor < 0xFFFF => {
+                            memory_cursor += 1;
                         }
                         crossterm::event::KeyCode::Char('k') => {
-                            memory_render_offset = memory_render_offset.saturating_sub(1);
+                            memory_cursor = memory_cursor.saturating_sub(1);
+                        }
+                        crossterm::event::KeyCode::Char('f') => {
+                            follow_pc = !follow_pc;
+                            if follow_pc {
+                                memory_cursor = lc3_state.pc as u16 as usize;
+                            }
                         }
                         crossterm::event::KeyCode::Char('n') => { 
-                            lc3_state.execute_next_instruction()?;
-                            // terminal.clear()?;
+                            running = false;
+                            status = match debugger.step(lc3_state) {
+                                Ok(()) => Status::default(),
+                                Err(e) => Status::Error(e.to_string()),
+                            };
+                        }
+                        crossterm::event::KeyCode::Char('s') => {
+                            running = false;
+                            match debugger.step_over(lc3_state) {
+                                Ok(returning) => {
+                                    running = returning;
+                                    status = Status::default();
+                                }
+                                Err(e) => status = Status::Error(e.to_string()),
+                            }
+                        }
+                        crossterm::event::KeyCode::Char('o') => {
+                            running = debugger.step_out(lc3_state);
+                            status = if running {
+                                Status::default()
+                            } else {
+                                Status::Error(String::from("Not inside a subroutine"))
+                            };
+                        }
+                        crossterm::event::KeyCode::Char('N') => {
+                            running = false;
+                            status = if lc3_state.step_back() {
+                                Status::default()
+                            } else {
+                                Status::Error(String::from("No earlier instructions recorded"))
+                            };
+                        }
+                        crossterm::event::KeyCode::Char('C') => {
+                            running = false;
+                            status = if debugger.run_back(lc3_state) {
+                                Status::Info(format!("Stopped at breakpoint x{:0>4X}", lc3_state.pc))
+                            } else {
+                                Status::Info(String::from("Reached the earliest recorded instruction"))
+                            };
+                        }
+                        crossterm::event::KeyCode::Char('c') => {
+                            running = !running && !lc3_state.halted();
+                            debugger.returning = None;
+                            status = Status::default();
+                        }
+                        crossterm::event::KeyCode::Char('b') => {
+                            let address = memory_cursor as u16;
+                            status = Status::Info(breakpoint_status(address, debugger.toggle_breakpoint(address)));
+                        }
+                        crossterm::event::KeyCode::Char('r') => {
+                            selected_register = match selected_register {
+                                Some(7) => None,
+                                Some(r) => Some(r + 1),
+                                None => Some(0),
+                            };
+                        }
+                        crossterm::event::KeyCode::Char('e') => {
+                            command_line = Some(match selected_register {
+                                Some(r) => format!("set R{} ", r),
+                                None => format!("set x{:0>4X} ", memory_cursor),
+                            });
                         }
                         crossterm::event::KeyCode::Char(':') => {
-                            memory_traverse_mode = true;
-                            memory_traverse_address = 0;
+                            command_line = Some(String::new());
                         }
                         crossterm::event::KeyCode::Char('q') => break,
                         _ => {}
@@ -230,9 +741,5 @@ pub fn render_tui(lc3_state: &mut State) -> Result<(), Box<dyn std::error::Error
         }
     }
 
-    // shutdown down: reset terminal back to original state
-    crossterm::execute!(std::io::stderr(), crossterm::terminal::LeaveAlternateScreen)?;
-    crossterm::terminal::disable_raw_mode()?;
-
     Ok(())
 }
@@ -14,8 +14,8 @@ pub fn bits(val: i16, start: u16, end: u16) -> u16 {
 pub fn sext(val: u16, len: u16) -> i16 {
     if val == 0 {
         0
-    } else if val < 2u16.pow((len - 1) as u32) as u16 {
-        return val as i16
+    } else if val < 2u16.pow((len - 1) as u32) {
+        val as i16
     } else {
         // 0000 0000 0001 0000
         // 1111 1111 1111 0000